@@ -1,95 +1,150 @@
 use web_time::{Duration, Instant};
 
-const MT: u64 = 600;
-//const MT: u64 = 10;
-const PFT: u64 = 90;
-//const PFT: u64 = 5;
-const OT_ROUNDS: u32 = 6;
-const HPFT: u64 = PFT / 2;
-const SOT: u64 = 120;
-//const SOT: u64 = 20;
-
-#[derive(PartialEq)]
-enum MatchStage {
-    FirstHalfPenaltyFree,
-    SecondHalfPenaltyFree,
-    Penalty,
-}
+use crate::engine::{
+    Adjustment, CoinToss, Fighter, MatchClock, MatchEngine, MatchStage, OvertimeState, Position,
+    RegulationState, Segment, Stoppage, Transition,
+};
+use crate::event_log::{EventLog, MatchEvent};
+use crate::fighters::{Corner, FighterDetails, Fighters};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::live::LiveState;
+use crate::live::{clock_text, seconds_left};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::overlay::{OverlayFiles, OverlayTemplate};
+use crate::replay::Replay;
+use crate::rules::{RuleSet, TiebreakPolicy};
+use crate::scoring::{ScoreChange, ScoreKind};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::server::ScoreboardServer;
+use crate::stalling::Passivity;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod scoreboard;
+
+const UNDO_SHORTCUT: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+const REDO_SHORTCUT: egui::KeyboardShortcut = egui::KeyboardShortcut::new(
+    egui::Modifiers::COMMAND.plus(egui::Modifiers::SHIFT),
+    egui::Key::Z,
+);
+const REDO_SHORTCUT_ALT: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y);
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct CjjTimer {
+    /// The rules the next match will be started with.
+    rules: RuleSet,
+    /// Where the setup screen loads and saves rule files.
+    rules_path: String,
+    /// Who fights in the next match.
+    fighters: Fighters,
+    engine: MatchEngine,
+    /// Set on startup when the last session ended in the middle of a match.
     #[serde(skip)]
-    start_non_engaged_instant: Instant,
-    #[serde(skip)]
-    total_non_engaged_duration: Duration,
-    #[serde(skip)]
-    start_regulation_instant: Instant,
-    #[serde(skip)]
-    total_regulation_duration: Duration,
-    #[serde(skip)]
-    total_overtime_duration: Duration,
-    #[serde(skip)]
-    start_overtime_instant: Instant,
-    #[serde(skip)]
-    regulation_input: u64,
-    #[serde(skip)]
-    regulation_duration: Duration,
+    resume_prompt: bool,
     #[serde(skip)]
-    penalty_free_input: u64,
+    warning: Option<String>,
     #[serde(skip)]
-    penalty_free_duration: Duration,
+    replay: Option<Replay>,
     #[serde(skip)]
-    half_penalty_free_duration: Duration,
+    adjust_dialog: Option<AdjustDialog>,
     #[serde(skip)]
-    total_penalty_duration: Duration,
+    stop_dialog: Option<StopDialog>,
+    /// Whether the audience scoreboard window is open.
+    #[cfg(not(target_arch = "wasm32"))]
+    scoreboard: bool,
+    /// Port the network scoreboard listens on.
+    #[cfg(not(target_arch = "wasm32"))]
+    server_port: u16,
+    /// Let devices on the network run the match too, given `remote_token`.
+    #[cfg(not(target_arch = "wasm32"))]
+    remote_control: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    remote_token: String,
+    /// What the OBS browser source shows.
+    #[cfg(not(target_arch = "wasm32"))]
+    overlay_template: OverlayTemplate,
+    /// Where the overlay window loads templates from.
+    #[cfg(not(target_arch = "wasm32"))]
+    overlay_template_path: String,
+    /// Where the overlay text files are written.
+    #[cfg(not(target_arch = "wasm32"))]
+    overlay_dir: String,
+    #[cfg(not(target_arch = "wasm32"))]
     #[serde(skip)]
-    penalty_time_divided: Duration,
+    overlay_files: Option<OverlayFiles>,
+    #[cfg(not(target_arch = "wasm32"))]
     #[serde(skip)]
-    standard_overtime_duration: Duration,
+    overlay_window: bool,
+    #[cfg(not(target_arch = "wasm32"))]
     #[serde(skip)]
-    standard_overtime_input: u64,
+    server: Option<ScoreboardServer>,
+    /// The coin toss as the table has entered it so far.
     #[serde(skip)]
-    over_time: bool,
+    toss: CoinToss,
     #[serde(skip)]
-    state: RegulationState,
-    #[serde(skip)]
-    overtime_state: OvertimeState,
-    #[serde(skip)]
-    overtime_segments: Vec<Segment>,
-    #[serde(skip)]
-    winner: Option<Fighter>,
+    submission: SubmissionDialog,
+}
+
+/// What the officials have entered in the "Adjust time" dialog so far.
+struct AdjustDialog {
+    clock: MatchClock,
+    seconds: i64,
 }
 
-#[derive(Debug)]
-enum Fighter {
-    A,
-    B,
+/// Who the table has entered as winning a regulation submission so far, and with what.
+struct SubmissionDialog {
+    winner: Fighter,
+    technique: usize,
+}
+
+/// What the officials have entered in the "End match" dialog so far.
+struct StopDialog {
+    winner: Fighter,
+    reason: Stoppage,
 }
 
 impl Default for CjjTimer {
     fn default() -> Self {
         Self {
-            start_non_engaged_instant: Instant::now(),
-            total_non_engaged_duration: Duration::from_secs(0),
-            start_regulation_instant: Instant::now(),
-            total_regulation_duration: Duration::from_secs(0),
-            regulation_input: MT,
-            regulation_duration: Duration::from_secs(MT),
-            penalty_free_input: PFT,
-            penalty_free_duration: Duration::from_secs(PFT),
-            total_penalty_duration: Duration::from_secs(0),
-            penalty_time_divided: Duration::from_secs(0),
-            total_overtime_duration: Duration::from_secs(0),
-            start_overtime_instant: Instant::now(),
-            half_penalty_free_duration: Duration::from_secs(HPFT),
-            standard_overtime_duration: Duration::from_secs(SOT),
-            standard_overtime_input: SOT,
-            over_time: false,
-            state: RegulationState::None,
-            overtime_state: OvertimeState::AdvanceOvertime,
-            overtime_segments: vec![],
-            winner: None,
+            rules: RuleSet::default(),
+            rules_path: "rules.toml".to_owned(),
+            fighters: Fighters::default(),
+            engine: MatchEngine::default(),
+            resume_prompt: false,
+            warning: None,
+            replay: None,
+            adjust_dialog: None,
+            stop_dialog: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            scoreboard: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            server_port: 8080,
+            #[cfg(not(target_arch = "wasm32"))]
+            remote_control: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            remote_token: new_token(),
+            #[cfg(not(target_arch = "wasm32"))]
+            overlay_template: OverlayTemplate::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            overlay_template_path: "overlay.html".to_owned(),
+            #[cfg(not(target_arch = "wasm32"))]
+            overlay_dir: "overlay".to_owned(),
+            #[cfg(not(target_arch = "wasm32"))]
+            overlay_files: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            overlay_window: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            server: None,
+            toss: CoinToss {
+                chooser: Fighter::A,
+                position: Position::Attack,
+            },
+            submission: SubmissionDialog {
+                winner: Fighter::A,
+                technique: 0,
+            },
         }
     }
 }
@@ -103,315 +158,722 @@ impl CjjTimer {
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
         if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+            let mut timer: Self = eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+            timer.resume_prompt = timer.engine.in_progress();
+            return timer;
         }
         Default::default()
     }
-}
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Segment {
-    Escape(Duration),
-    Submission(Duration),
-}
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum RegulationState {
-    Start,
-    Restarted,
-    NotEngaged,
-    Paused,
-    Engaged,
-    Overtime,
-    Submission,
-    None,
-}
-
-#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
-enum Transition {
-    StartRegulation,
-    StartOvertime,
-    Restart,
-    Pause,
-    Engage,
-    Separate,
-    TimeExpire,
-    Submission,
-    Undo,
-    Win,
-}
-
-#[derive(Copy, PartialEq, Clone)]
-enum OvertimeState {
-    Paused,
-    Engaged,
-    Escaped,
-    Submission,
-    AdvanceOvertime,
-    Win,
-}
 
-impl CjjTimer {
-    fn change_regulation_state(&self, event: Transition) -> RegulationState {
-        match (self.state, event) {
-            (RegulationState::None, Transition::StartRegulation) => RegulationState::Start,
-            (RegulationState::Restarted, Transition::StartRegulation) => RegulationState::Start,
-
-            (RegulationState::Start, Transition::Separate)
-            | (RegulationState::Paused, Transition::Separate)
-            | (RegulationState::Engaged, Transition::Separate) => RegulationState::NotEngaged,
-
-            (RegulationState::NotEngaged, Transition::Engage) => RegulationState::Engaged,
-
-            (RegulationState::Engaged, Transition::Pause)
-            | (RegulationState::NotEngaged, Transition::Pause) => RegulationState::Paused,
-
-            (RegulationState::Engaged, Transition::Submission) => RegulationState::Submission,
-
-            (RegulationState::Engaged, Transition::TimeExpire)
-            | (RegulationState::NotEngaged, Transition::TimeExpire) => RegulationState::Overtime,
+    /// Apply a button press, keeping the match as it is if the engine rejects it.
+    fn apply(&mut self, event: Transition) {
+        match self.engine.apply(event) {
+            Ok(_) => self.warning = None,
+            Err(error) => self.warning = Some(error.to_string()),
+        }
+    }
 
-            (RegulationState::Submission, Transition::Restart) => RegulationState::Restarted,
-            (RegulationState::Submission, Transition::Undo) => RegulationState::NotEngaged,
+    /// Replace the blank match with one for the rules and fighters entered on setup.
+    fn set_up_match(&mut self) {
+        self.engine = MatchEngine::new(self.rules.clone()).with_fighters(self.fighters.clone());
+    }
 
-            _ => RegulationState::None,
+    fn undo(&mut self) {
+        if self.engine.undo().is_some() {
+            self.warning = None;
         }
     }
 
-    fn regulation_input(&self, event: Transition) -> Transition {
-        if let RegulationState::Start = self.state {
-            return Transition::Separate;
+    fn redo(&mut self) {
+        if self.engine.redo().is_some() {
+            self.warning = None;
         }
-        if let (true, s) = (self.over_time, self.state) {
-            match s {
-                RegulationState::Engaged => {
-                    return Transition::TimeExpire;
-                }
-                RegulationState::NotEngaged => {
-                    return Transition::TimeExpire;
-                }
-                _ => {
-                    return event;
-                }
-            };
-        }
-        event
     }
 
-    fn change_regulation(&mut self, event: Transition) {
-        let new_state = self.change_regulation_state(self.regulation_input(event));
-        match new_state {
-            RegulationState::Start => {
-                self.over_time = false;
-                self.start_non_engaged_instant = Instant::now();
-                self.total_non_engaged_duration = Duration::from_secs(0);
-                self.start_regulation_instant = Instant::now();
-                self.total_regulation_duration = Duration::from_secs(0);
-                self.total_penalty_duration = Duration::from_secs(0);
-                self.half_penalty_free_duration = Duration::from_secs(self.penalty_free_input / 2);
-            }
-            RegulationState::NotEngaged => {
-                match self.state {
-                    RegulationState::Paused | RegulationState::Submission => {}
-                    _ => self.total_regulation_duration += self.start_regulation_instant.elapsed(),
+    /// Start or stop the network scoreboard, and show where to find it.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn server_controls(&mut self, ui: &mut egui::Ui) {
+        let mut running = self.server.is_some();
+        if ui.toggle_value(&mut running, "Network").changed() {
+            self.server = None;
+            if running {
+                let ctx = ui.ctx().clone();
+                let started = if self.remote_control {
+                    ScoreboardServer::start_with_commands(
+                        self.server_port,
+                        &self.remote_token,
+                        move || ctx.request_repaint(),
+                    )
+                } else {
+                    ScoreboardServer::start(self.server_port)
+                };
+                match started {
+                    Ok(server) => self.server = Some(server),
+                    Err(error) => {
+                        self.warning =
+                            Some(format!("Couldn't start the network scoreboard: {}", error));
+                    }
                 }
-                self.start_regulation_instant = Instant::now();
-                self.start_non_engaged_instant = Instant::now();
             }
-            RegulationState::Engaged => {
-                self.total_regulation_duration += self.start_regulation_instant.elapsed();
-                self.total_non_engaged_duration += self.start_non_engaged_instant.elapsed();
-                self.start_regulation_instant = Instant::now();
-                self.start_non_engaged_instant = Instant::now();
-            }
-            RegulationState::Paused => {
-                if RegulationState::Engaged == self.state {
-                    self.total_regulation_duration += self.start_regulation_instant.elapsed();
-                    self.start_non_engaged_instant = Instant::now();
-                    self.start_regulation_instant = Instant::now();
-                }
-                if RegulationState::NotEngaged == self.state {
-                    self.total_regulation_duration += self.start_regulation_instant.elapsed();
-                    self.total_non_engaged_duration += self.start_non_engaged_instant.elapsed();
-                    self.start_non_engaged_instant = Instant::now();
-                    self.start_regulation_instant = Instant::now();
+        }
+        match &self.server {
+            Some(server) => {
+                ui.hyperlink(server.url());
+                if server.takes_commands() {
+                    ui.hyperlink_to("Remote", format!("{}remote", server.url()));
+                    ui.label(format!("Token: {}", self.remote_token));
                 }
             }
-            RegulationState::Overtime => {
-                self.total_regulation_duration += self.start_regulation_instant.elapsed();
-                if RegulationState::NotEngaged == self.state {
-                    self.total_non_engaged_duration += self.start_non_engaged_instant.elapsed();
-                }
-                if self.total_non_engaged_duration > self.penalty_free_duration {
-                    self.total_penalty_duration =
-                        self.total_non_engaged_duration - self.penalty_free_duration;
-                    self.penalty_time_divided = self.total_penalty_duration / OT_ROUNDS;
+            None => {
+                ui.add(egui::DragValue::new(&mut self.server_port).prefix("port "));
+                ui.checkbox(&mut self.remote_control, "Remote control");
+                if self.remote_control && ui.small_button("New token").clicked() {
+                    self.remote_token = new_token();
                 }
-                self.change_overtime(self.overtime_input(event));
             }
-            RegulationState::Restarted => {}
-            RegulationState::Submission => {
-                if RegulationState::Engaged == self.state {
-                    self.total_regulation_duration += self.start_regulation_instant.elapsed();
-                    self.start_non_engaged_instant = Instant::now();
-                    self.start_regulation_instant = Instant::now();
-                }
+        }
+    }
+
+    /// Send the match to the network displays and the overlay text files, if they are on.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn publish(&mut self, now: Instant) {
+        if self.server.is_none() && self.overlay_files.is_none() {
+            return;
+        }
+        let live = LiveState::new(&self.engine, now);
+        if let Some(server) = &self.server {
+            server.publish(&live);
+            server.publish_overlay(self.overlay_template.render(&live));
+        }
+        if let Some(files) = &mut self.overlay_files {
+            if let Err(error) = files.write(&live) {
+                self.warning = Some(format!("Couldn't write the overlay files: {}", error));
+                self.overlay_files = None;
             }
-            RegulationState::None => {}
         }
-        self.state = new_state;
     }
 
-    fn change_overtime_state(&mut self, event: Transition) -> OvertimeState {
-        match (self.overtime_state, event) {
-            (OvertimeState::AdvanceOvertime, Transition::Engage) => OvertimeState::Engaged,
+    /// The overlay template, the text files and where OBS finds the overlay page.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn overlay_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.overlay_window;
+        egui::Window::new("Overlay")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label("Template:");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.overlay_template_path);
+                    if ui.button("Load").clicked() {
+                        match OverlayTemplate::load(std::path::Path::new(
+                            &self.overlay_template_path,
+                        )) {
+                            Ok(template) => self.overlay_template = template,
+                            Err(error) => {
+                                self.warning =
+                                    Some(format!("Couldn't load the template: {}", error));
+                            }
+                        }
+                    }
+                    if ui.button("Built-in").clicked() {
+                        self.overlay_template = OverlayTemplate::default();
+                    }
+                });
+                match &self.server {
+                    Some(server) => {
+                        ui.label("OBS browser source:");
+                        ui.hyperlink(format!("{}overlay", server.url()));
+                    }
+                    None => {
+                        ui.label("Turn on Network to serve the overlay page");
+                    }
+                }
+                ui.separator();
+                let mut writing = self.overlay_files.is_some();
+                ui.horizontal(|ui| {
+                    let toggled = ui.checkbox(&mut writing, "Write text files to").changed();
+                    ui.add_enabled(!writing, egui::TextEdit::singleline(&mut self.overlay_dir));
+                    if !toggled {
+                        return;
+                    }
+                    self.overlay_files = None;
+                    if writing {
+                        match OverlayFiles::new(&self.overlay_dir) {
+                            Ok(files) => self.overlay_files = Some(files),
+                            Err(error) => {
+                                self.warning =
+                                    Some(format!("Couldn't write the overlay files: {}", error));
+                            }
+                        }
+                    }
+                });
+            });
+        self.overlay_window = open;
+    }
 
-            (OvertimeState::Engaged, Transition::Separate) => OvertimeState::Escaped,
+    /// Apply the commands devices on the network have sent since the last frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn remote_commands(&mut self) {
+        while let Some(command) = self
+            .server
+            .as_ref()
+            .and_then(ScoreboardServer::next_command)
+        {
+            if self.resume_prompt {
+                command.refuse("the saved match has not been resumed or discarded yet");
+                continue;
+            }
+            // Starting or ending a match from setup works like the Start and End match
+            // buttons, with the rules and fighters entered there.
+            if RegulationState::None == self.engine.state()
+                && matches!(
+                    command.event,
+                    Transition::StartRegulation | Transition::Stop { .. }
+                )
+            {
+                if let Err(error) = self.rules.validate() {
+                    command.refuse(&error.to_string());
+                    continue;
+                }
+                self.set_up_match();
+            }
+            let result = self.engine.apply(command.event);
+            command.reply(result);
+        }
+    }
+}
 
-            (OvertimeState::Engaged, Transition::Submission) => OvertimeState::Submission,
+fn integer_edit_field(ui: &mut egui::Ui, duration: &mut Duration) -> egui::Response {
+    let mut tmp_value = format!("{:?}", duration.as_secs());
+    let res = ui.text_edit_singleline(&mut tmp_value);
+    if let Ok(result) = tmp_value.parse() {
+        *duration = Duration::from_secs(result);
+    }
+    res
+}
 
-            (OvertimeState::Engaged, Transition::TimeExpire) => OvertimeState::AdvanceOvertime,
+/// The tiebreak rule of the match, for the screens that end overtime.
+fn tiebreak_label(ui: &mut egui::Ui, engine: &MatchEngine) {
+    ui.label(format!("Tiebreak: {}", engine.rules().tiebreak.label()));
+}
 
-            (OvertimeState::Engaged, Transition::Win) => OvertimeState::Win,
+/// Every overtime segment so far, with the fighter it counts for.
+fn segment_list(ui: &mut egui::Ui, engine: &MatchEngine) {
+    for (i, segment) in engine.overtime_segments().iter().enumerate() {
+        let fighter = engine.segment_fighter(i);
+        ui.horizontal(|ui| {
+            corner_marker(ui, engine.fighters().get(fighter).corner);
+            let (ending, time) = match *segment {
+                Segment::Escape(time) => ("Escape", time),
+                Segment::Submission(time) => ("Submission", time),
+            };
+            let name = engine.fighters().name(fighter);
+            ui.label(format_time(
+                &format!("{} round {} {}", name, i / 2 + 1, ending),
+                time,
+            ));
+        });
+    }
+}
 
-            (OvertimeState::Engaged, Transition::Pause) => OvertimeState::Paused,
+fn corner_colour(corner: Corner) -> egui::Color32 {
+    match corner {
+        Corner::Red => egui::Color32::RED,
+        Corner::Blue => egui::Color32::from_rgb(0, 90, 255),
+        Corner::White => egui::Color32::WHITE,
+        Corner::Black => egui::Color32::BLACK,
+        Corner::Green => egui::Color32::GREEN,
+        Corner::Yellow => egui::Color32::YELLOW,
+    }
+}
 
-            (OvertimeState::Paused, Transition::Engage) => OvertimeState::Engaged,
+/// A square in the colour of the fighter's corner, to put next to their name.
+fn corner_marker(ui: &mut egui::Ui, corner: Corner) {
+    ui.colored_label(corner_colour(corner), "■");
+}
 
-            (OvertimeState::Escaped, Transition::Undo) => {
-                self.overtime_segments.pop();
-                OvertimeState::Engaged
+/// Both fighters with their team and weight class, shown above every match screen.
+///
+/// In a points division the scores are shown too, with buttons to change them while
+/// regulation is on. Returns the change once one is clicked.
+fn fighters_header(ui: &mut egui::Ui, engine: &MatchEngine) -> Option<ScoreChange> {
+    let fighters = engine.fighters();
+    let scoring_open = matches!(
+        engine.state(),
+        RegulationState::NotEngaged | RegulationState::Engaged | RegulationState::Paused
+    );
+    let mut change = None;
+    for fighter in [Fighter::A, Fighter::B] {
+        let details = fighters.get(fighter);
+        ui.horizontal(|ui| {
+            corner_marker(ui, details.corner);
+            let mut label = fighters.name(fighter);
+            for extra in [&details.team, &details.weight_class] {
+                if !extra.trim().is_empty() {
+                    label.push_str(" · ");
+                    label.push_str(extra.trim());
+                }
             }
-            (OvertimeState::Submission, Transition::Undo) => {
-                self.overtime_segments.pop();
-                OvertimeState::Engaged
+            ui.label(label);
+            if !engine.rules().scoring {
+                return;
             }
-            (OvertimeState::Win, Transition::Undo) => {
-                self.overtime_segments.pop();
-                OvertimeState::Engaged
+            let score = engine.scores().get(fighter);
+            for (kind, name, counts) in [
+                (ScoreKind::Point, "Points", &[2, 3, 4, -1][..]),
+                (ScoreKind::Advantage, "Advantages", &[1, -1][..]),
+                (ScoreKind::Penalty, "Penalties", &[1, -1][..]),
+            ] {
+                ui.separator();
+                ui.label(format!("{}: {}", name, score.get(kind)));
+                if !scoring_open {
+                    continue;
+                }
+                for &count in counts {
+                    if ui.small_button(format!("{:+}", count)).clicked() {
+                        change = Some(ScoreChange {
+                            fighter,
+                            kind,
+                            count,
+                        });
+                    }
+                }
             }
+        });
+    }
+    change
+}
 
-            _ => OvertimeState::AdvanceOvertime,
+/// Who is held responsible for not engaging, with each fighter's stalling time.
+///
+/// Returns the new choice once the officials change it.
+fn passivity_selector(ui: &mut egui::Ui, engine: &MatchEngine, now: Instant) -> Option<Passivity> {
+    let fighters = engine.fighters();
+    let mut passivity = engine.passivity();
+    ui.horizontal(|ui| {
+        ui.label("Responsible:");
+        ui.selectable_value(&mut passivity, Passivity::Both, "Both");
+        for fighter in [Fighter::A, Fighter::B] {
+            ui.selectable_value(
+                &mut passivity,
+                Passivity::Fighter(fighter),
+                fighters.name(fighter),
+            );
         }
+    });
+    for fighter in [Fighter::A, Fighter::B] {
+        ui.label(format_time(
+            &format!("{} Stalling Time", fighters.name(fighter)),
+            engine.stalling_time(fighter, now),
+        ));
     }
+    (passivity != engine.passivity()).then_some(passivity)
+}
+
+/// A button per fighter to call an injury timeout, with what is left of their allowance.
+fn injury_timeout_buttons(
+    ui: &mut egui::Ui,
+    engine: &MatchEngine,
+    now: Instant,
+) -> Option<Fighter> {
+    let mut called = None;
+    ui.horizontal(|ui| {
+        for fighter in [Fighter::A, Fighter::B] {
+            let label = format_time_left(
+                &format!("Injury timeout {}", engine.fighters().name(fighter)),
+                engine.injury_time_left(fighter, now),
+            );
+            if ui.small_button(label).clicked() {
+                called = Some(fighter);
+            }
+        }
+    });
+    called
+}
 
-    fn overtime_input(&self, event: Transition) -> Transition {
-        event
+/// The running injury timeout, counting down what is left of the fighter's allowance.
+///
+/// Returns the end of the timeout, or an injury stoppage, once the officials call it.
+fn injury_timeout_panel(
+    ui: &mut egui::Ui,
+    engine: &MatchEngine,
+    fighter: Fighter,
+    now: Instant,
+) -> Option<Transition> {
+    let fighters = engine.fighters();
+    let name = fighters.name(fighter);
+    let mut event = None;
+    ui.label(format!("INJURY TIMEOUT for {}", name));
+    ui.label(format_time_left(
+        "Injury Time Left",
+        engine.injury_time_left(fighter, now),
+    ));
+    if engine.injury_allowance_exhausted(fighter, now) {
+        ui.colored_label(
+            egui::Color32::RED,
+            format!("{} has no injury time left", name),
+        );
+    }
+    if ui.button("Resume Match").clicked() {
+        event = Some(Transition::EndInjuryTimeout);
     }
-    fn change_overtime(&mut self, event: Transition) {
-        let new_state = self.change_overtime_state(self.overtime_input(event));
-        match new_state {
-            OvertimeState::Engaged => {
-                match self.overtime_state {
-                    OvertimeState::Paused
-                    | OvertimeState::Submission
-                    | OvertimeState::Escaped
-                    | OvertimeState::Win => {}
-                    _ => {
-                        self.total_overtime_duration += self.start_overtime_instant.elapsed();
+    let winner = fighter.other();
+    if ui
+        .button(format!("Injury Stoppage, {} wins", fighters.name(winner)))
+        .clicked()
+    {
+        event = Some(Transition::Stop {
+            winner,
+            reason: Stoppage::Injury,
+        });
+    }
+    event
+}
+
+/// A hard to guess token for remote control.
+///
+/// `RandomState` keys come from the operating system's random numbers.
+#[cfg(not(target_arch = "wasm32"))]
+fn new_token() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let random = || {
+        std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish()
+    };
+    format!("{:016x}{:016x}", random(), random())
+}
+
+/// The setup form for one fighter.
+fn fighter_form(ui: &mut egui::Ui, fighter: Fighter, details: &mut FighterDetails) {
+    egui::Grid::new(("fighter_form", fighter))
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Name:");
+            ui.text_edit_singleline(&mut details.name);
+            ui.end_row();
+            ui.label("Team:");
+            ui.text_edit_singleline(&mut details.team);
+            ui.end_row();
+            ui.label("Weight Class:");
+            ui.text_edit_singleline(&mut details.weight_class);
+            ui.end_row();
+            ui.label("Corner:");
+            egui::ComboBox::from_id_source(("corner", fighter))
+                .selected_text(details.corner.label())
+                .show_ui(ui, |ui| {
+                    for corner in Corner::ALL {
+                        ui.selectable_value(&mut details.corner, corner, corner.label());
                     }
-                }
-                self.start_overtime_instant = Instant::now();
-            }
-            OvertimeState::Paused => {
-                if OvertimeState::Engaged == self.overtime_state {
-                    self.total_overtime_duration += self.start_overtime_instant.elapsed();
-                    self.start_overtime_instant = Instant::now();
-                }
-            }
-            OvertimeState::AdvanceOvertime => {}
-            OvertimeState::Submission => {
-                if OvertimeState::Engaged == self.overtime_state {
-                    self.total_overtime_duration += self.start_overtime_instant.elapsed();
-                    self.start_overtime_instant = Instant::now();
-                }
-            }
-            OvertimeState::Escaped => {
-                if OvertimeState::Engaged == self.overtime_state {
-                    self.total_overtime_duration += self.start_overtime_instant.elapsed();
-                    self.start_overtime_instant = Instant::now();
-                }
-            }
-            OvertimeState::Win => {
-                if OvertimeState::Engaged == self.overtime_state {
-                    self.total_overtime_duration += self.start_overtime_instant.elapsed();
-                    self.start_overtime_instant = Instant::now();
-                }
+                });
+            ui.end_row();
+        });
+}
+
+/// Toss a coin for who gets to choose where to start overtime.
+fn draw_fighter() -> Fighter {
+    #[cfg(not(target_arch = "wasm32"))]
+    let heads = {
+        use std::hash::{BuildHasher, Hasher};
+        std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish()
+            % 2
+            == 0
+    };
+    #[cfg(target_arch = "wasm32")]
+    let heads = js_sys::Math::random() < 0.5;
+    if heads {
+        Fighter::A
+    } else {
+        Fighter::B
+    }
+}
+
+/// Pick one of the built-in rule profiles.
+fn profile_selector(ui: &mut egui::Ui, rules: &mut RuleSet) {
+    egui::ComboBox::from_label("Rules")
+        .selected_text(rules.name.clone())
+        .show_ui(ui, |ui| {
+            for profile in RuleSet::profiles() {
+                let name = profile.name.clone();
+                ui.selectable_value(rules, profile, name);
             }
+        });
+}
+
+/// Load the rules from, or save them to, a `.toml` or `.json` file.
+#[cfg(not(target_arch = "wasm32"))]
+fn rule_file_buttons(
+    ui: &mut egui::Ui,
+    rules: &mut RuleSet,
+    path: &mut String,
+) -> Result<(), crate::rules::RuleSetError> {
+    let mut result = Ok(());
+    ui.horizontal(|ui| {
+        ui.label("Rule file:");
+        ui.text_edit_singleline(path);
+        if ui.button("Load").clicked() {
+            result = RuleSet::load(std::path::Path::new(path)).map(|loaded| *rules = loaded);
+        }
+        if ui.button("Save").clicked() {
+            result = rules.save(std::path::Path::new(path));
         }
-        self.overtime_state = new_state;
+    });
+    result
+}
+fn format_time(label: &str, duration: Duration) -> String {
+    format!("{}: {}", label, clock_text(duration.as_secs()))
+}
+
+/// Like [`format_time`] for a clock counting down, which shows a started second.
+fn format_time_left(label: &str, left: Duration) -> String {
+    format!("{}: {}", label, clock_text(seconds_left(left)))
+}
+
+fn stage_colour(stage: MatchStage) -> egui::Color32 {
+    match stage {
+        MatchStage::FirstHalfPenaltyFree => egui::Color32::GREEN,
+        MatchStage::SecondHalfPenaltyFree => egui::Color32::KHAKI,
+        MatchStage::Penalty => egui::Color32::RED,
     }
-    fn calculate_win(&mut self) -> bool {
-        if self.overtime_segments.len() % 2 == 0 {
-            let a: Segment = self.overtime_segments[self.overtime_segments.len() - 2];
-            let b: Segment = self.overtime_segments[self.overtime_segments.len() - 1];
-            match (a, b) {
-                (Segment::Submission(t1), Segment::Submission(t2)) => {
-                    if t1 < t2 {
-                        self.winner = Some(Fighter::A);
-                        true
-                    } else {
-                        self.winner = Some(Fighter::B);
-                        true
-                    }
-                }
-                (Segment::Submission(_), Segment::Escape(_)) => {
-                    self.winner = Some(Fighter::A);
-                    true
-                }
-                (Segment::Escape(_), Segment::Submission(_)) => {
-                    self.winner = Some(Fighter::B);
-                    true
-                }
-                (Segment::Escape(_), Segment::Escape(_)) => {
-                    if self.overtime_segments.len() >= 6 {
-                        let mut a: Duration = Duration::from_secs(0);
-                        let mut b: Duration = Duration::from_secs(0);
-                        for (i, segment) in self.overtime_segments.iter().enumerate() {
-                            if i % 2 == 0 {
-                                if let Segment::Escape(t) = segment {
-                                    a += *t;
-                                }
-                            } else if let Segment::Escape(t) = segment {
-                                b += *t;
-                            }
-                        }
-                        //let a: Duration = self.overtime_segments.iter().enumerate().filter_map(|(index, value)| if index % 2 == 0 { Some(value) } else { None } ).sum();
-                        //let b: Duration = self.overtime_segments.iter().enumerate().filter_map(|(index, value)| if index % 1 == 0 { Some(value) } else { None } ).sum();
-                        if a == b {
-                            return false;
-                        }
-                        if a > b {
-                            self.winner = Some(Fighter::B);
-                        } else {
-                            self.winner = Some(Fighter::A);
-                        }
-                        return true;
-                    }
-                    false
-                }
+}
+
+fn penalty_label(ui: &mut egui::Ui, engine: &MatchEngine, now: Instant) {
+    let stage = engine.match_stage(now);
+    let text = match stage {
+        MatchStage::FirstHalfPenaltyFree => {
+            format_time("1st Penalty Free Time", engine.non_engaged_time(now))
+        }
+        MatchStage::SecondHalfPenaltyFree => {
+            format_time("2nd Penalty Free Time", engine.non_engaged_time(now))
+        }
+        MatchStage::Penalty => format_time("Penalty Time", engine.penalty_time(now)),
+    };
+    ui.colored_label(stage_colour(stage), text);
+}
+
+fn replay_window(ctx: &egui::Context, open: &mut bool, replay: &mut Replay) {
+    egui::Window::new("Replay").open(open).show(ctx, |ui| {
+        let mut position = replay.position().as_secs_f32();
+        let length = replay.length().as_secs_f32();
+        if ui
+            .add(egui::Slider::new(&mut position, 0.0..=length).text("seconds"))
+            .changed()
+        {
+            replay.seek(Duration::from_secs_f32(position));
+        }
+        let engine = replay.engine();
+        let now = engine.now();
+        ui.label(format!("Regulation: {:?}", engine.state()));
+        ui.label(format_time("Current Time", engine.regulation_time(now)));
+        ui.label(format_time(
+            "Non Engaged Time",
+            engine.non_engaged_time(now),
+        ));
+        ui.label(format_time("Penalty Time", engine.penalty_time(now)));
+        if RegulationState::Overtime == engine.state() {
+            ui.label(format!("Overtime: {:?}", engine.overtime_state()));
+            ui.label(format_time(
+                "Penalty Time Divided",
+                engine.penalty_time_divided(),
+            ));
+            ui.label(format_time("Segment Time", engine.overtime_time(now)));
+            segment_list(ui, engine);
+        }
+        if let Some(winner) = engine.winner() {
+            ui.label(format!("The Winner is: {}", engine.fighters().name(winner)));
+        }
+    });
+}
+
+/// Returns the stop transition once the officials confirm it.
+fn stop_window(
+    ctx: &egui::Context,
+    open: &mut bool,
+    dialog: &mut StopDialog,
+    fighters: &Fighters,
+) -> Option<Transition> {
+    let mut stop = None;
+    egui::Window::new("End match").open(open).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Winner:");
+            for fighter in [Fighter::A, Fighter::B] {
+                ui.radio_value(&mut dialog.winner, fighter, fighters.name(fighter));
             }
-        } else {
-            false
+        });
+        for reason in Stoppage::ALL {
+            ui.radio_value(&mut dialog.reason, reason, reason.label());
+        }
+        if ui.button("End match").clicked() {
+            stop = Some(Transition::Stop {
+                winner: dialog.winner,
+                reason: dialog.reason,
+            });
         }
+    });
+    stop
+}
+
+/// The winner, how they won and the time it ended, once the match has a result.
+fn result_label(ui: &mut egui::Ui, engine: &MatchEngine) {
+    if let Some(result) = engine.result() {
+        ui.horizontal(|ui| {
+            corner_marker(ui, engine.fighters().get(result.winner).corner);
+            ui.label(format!(
+                "The Winner is: {} by {}",
+                engine.fighters().name(result.winner),
+                result.method.label(engine.rules())
+            ));
+        });
+        ui.label(format_time("Ended at", result.time));
     }
 }
 
-fn integer_edit_field(
+/// Who won a regulation submission and with what.
+///
+/// Returns the result once the table confirms it.
+fn submission_form(
     ui: &mut egui::Ui,
-    value: &mut u64,
-    duration: &mut Duration,
-) -> egui::Response {
-    let mut tmp_value = format!("{:?}", value);
-    let res = ui.text_edit_singleline(&mut tmp_value);
-    if let Ok(result) = tmp_value.parse() {
-        *value = result;
-        *duration = Duration::from_secs(*value);
-    }
-    res
+    dialog: &mut SubmissionDialog,
+    engine: &MatchEngine,
+) -> Option<Transition> {
+    let submissions = &engine.rules().submissions;
+    ui.horizontal(|ui| {
+        ui.label("Winner:");
+        for fighter in [Fighter::A, Fighter::B] {
+            ui.radio_value(&mut dialog.winner, fighter, engine.fighters().name(fighter));
+        }
+    });
+    egui::ComboBox::from_label("Technique")
+        .selected_text(submissions.get(dialog.technique).map_or("", String::as_str))
+        .show_ui(ui, |ui| {
+            for (index, technique) in submissions.iter().enumerate() {
+                ui.selectable_value(&mut dialog.technique, index, technique);
+            }
+        });
+    ui.label(format_time(
+        "Submission at",
+        engine.total_regulation_duration(),
+    ));
+    ui.button("Confirm Submission")
+        .clicked()
+        .then_some(Transition::Submitted {
+            winner: dialog.winner,
+            technique: dialog.technique,
+        })
 }
-fn format_time(label: &str, duration: Duration) -> String {
-    let seconds = duration.as_secs() % 60;
-    let minutes = (duration.as_secs() / 60) % 60;
-    format!("{}: {:02}:{:02}", label, minutes, seconds)
+
+/// Returns the adjustment to make once the officials confirm one.
+fn adjust_window(
+    ctx: &egui::Context,
+    open: &mut bool,
+    dialog: &mut AdjustDialog,
+    engine: &MatchEngine,
+    now: Instant,
+) -> Option<Adjustment> {
+    let mut adjustment = None;
+    egui::Window::new("Adjust time").open(open).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut dialog.clock, MatchClock::Regulation, "Match Time");
+            ui.radio_value(
+                &mut dialog.clock,
+                MatchClock::NonEngaged,
+                "Non Engaged Time",
+            );
+            ui.radio_value(&mut dialog.clock, MatchClock::Overtime, "Segment Time");
+        });
+        let current = match dialog.clock {
+            MatchClock::Regulation => engine.regulation_time(now),
+            MatchClock::NonEngaged => engine.non_engaged_time(now),
+            MatchClock::Overtime => engine.overtime_time(now),
+        };
+        ui.label(format_time("Current", current));
+        if MatchClock::NonEngaged == dialog.clock {
+            let responsible = match engine.passivity() {
+                Passivity::Both => "both fighters".to_owned(),
+                Passivity::Fighter(fighter) => engine.fighters().name(fighter),
+            };
+            ui.label(format!("Counts as stalling for {}", responsible));
+        }
+        ui.add(
+            egui::DragValue::new(&mut dialog.seconds)
+                .clamp_range(0..=600)
+                .suffix(" s"),
+        );
+        ui.horizontal(|ui| {
+            if ui.button("Add").clicked() {
+                adjustment = Some(Adjustment {
+                    clock: dialog.clock,
+                    seconds: dialog.seconds,
+                });
+            }
+            if ui.button("Subtract").clicked() {
+                adjustment = Some(Adjustment {
+                    clock: dialog.clock,
+                    seconds: -dialog.seconds,
+                });
+            }
+        });
+    });
+    adjustment
+}
+
+fn format_wall_clock(wall_clock_ms: u64) -> String {
+    let seconds = (wall_clock_ms / 1000) % (24 * 60 * 60);
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+/// Returns true if the officials asked to replay the log.
+fn event_log_panel(ui: &mut egui::Ui, log: &EventLog) -> bool {
+    let mut replay = false;
+    egui::CollapsingHeader::new(format!("Match log ({})", log.len())).show(ui, |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("Copy as JSON").clicked() {
+                match log.to_json() {
+                    Ok(json) => ui.output_mut(|o| o.copied_text = json),
+                    Err(err) => log::error!("Failed to serialize match log: {}", err),
+                }
+            }
+            replay = ui.button("Replay").clicked();
+        });
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                egui::Grid::new("event_log_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("UTC");
+                        ui.label("Match");
+                        ui.label("Event");
+                        ui.label("State");
+                        ui.end_row();
+                        for entry in log.entries() {
+                            ui.label(format_wall_clock(entry.wall_clock_ms));
+                            ui.label(format!("{:.1}s", entry.at.as_secs_f32()));
+                            match entry.event {
+                                MatchEvent::Transition(transition) => {
+                                    ui.label(format!("{:?}", transition))
+                                }
+                                event => ui.label(format!("{:?}", event)),
+                            };
+                            match entry.state.regulation {
+                                RegulationState::Overtime => {
+                                    ui.label(format!("Overtime {:?}", entry.state.overtime))
+                                }
+                                regulation => ui.label(format!("{:?}", regulation)),
+                            };
+                            ui.end_row();
+                        }
+                    });
+            });
+    });
+    replay
 }
 
 impl eframe::App for CjjTimer {
@@ -420,8 +882,33 @@ impl eframe::App for CjjTimer {
         eframe::set_value(storage, eframe::APP_KEY, self);
     }
 
+    /// Save often, so a crash loses as little of a running match as possible.
+    fn auto_save_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(1)
+    }
+
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.remote_commands();
+        let now = self.engine.now();
+        self.engine.tick(now);
+
+        // A text field being typed in keeps its own undo and redo.
+        if !ctx.wants_keyboard_input() {
+            // Redo first: its shortcut also matches undo's, which ignores the extra Shift.
+            let redo = ctx.input_mut(|i| {
+                let redo = i.consume_shortcut(&REDO_SHORTCUT);
+                i.consume_shortcut(&REDO_SHORTCUT_ALT) || redo
+            });
+            if redo && self.engine.can_redo() {
+                self.redo();
+            }
+            if self.engine.can_undo() && ctx.input_mut(|i| i.consume_shortcut(&UNDO_SHORTCUT)) {
+                self.undo();
+            }
+        }
+
         // Put your widgets into a `SidePanel`, `TopBottomPanel`, `CentralPanel`, `Window` or `Area`.
         // For inspiration and more examples, go to https://emilk.github.io/egui
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
@@ -440,9 +927,117 @@ impl eframe::App for CjjTimer {
                 }
 
                 egui::widgets::global_dark_light_mode_buttons(ui);
+                ui.add_space(16.0);
+
+                if ui
+                    .add_enabled(self.engine.can_undo(), egui::Button::new("Undo"))
+                    .on_hover_text(ctx.format_shortcut(&UNDO_SHORTCUT))
+                    .clicked()
+                {
+                    self.undo();
+                }
+                if ui
+                    .add_enabled(self.engine.can_redo(), egui::Button::new("Redo"))
+                    .on_hover_text(ctx.format_shortcut(&REDO_SHORTCUT))
+                    .clicked()
+                {
+                    self.redo();
+                }
+                if ui
+                    .add_enabled(
+                        RegulationState::None != self.engine.state(),
+                        egui::Button::new("Adjust time"),
+                    )
+                    .clicked()
+                {
+                    self.adjust_dialog = Some(AdjustDialog {
+                        clock: MatchClock::Regulation,
+                        seconds: 0,
+                    });
+                }
+                if ui
+                    .add_enabled(
+                        self.engine.can_stop()
+                            && (RegulationState::None != self.engine.state()
+                                || self.rules.validate().is_ok()),
+                        egui::Button::new("End match"),
+                    )
+                    .clicked()
+                {
+                    self.stop_dialog = Some(StopDialog {
+                        winner: Fighter::A,
+                        reason: Stoppage::RefereeDecision,
+                    });
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.toggle_value(&mut self.scoreboard, "Scoreboard");
+                #[cfg(not(target_arch = "wasm32"))]
+                self.server_controls(ui);
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.toggle_value(&mut self.overlay_window, "Overlay");
             });
         });
 
+        #[cfg(not(target_arch = "wasm32"))]
+        self.publish(now);
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.overlay_window {
+            self.overlay_window(ctx);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.scoreboard {
+            self.scoreboard = scoreboard::show(ctx, &self.engine, now);
+        }
+
+        egui::TopBottomPanel::bottom("event_log").show(ctx, |ui| {
+            if event_log_panel(ui, self.engine.log()) {
+                match Replay::new(&self.engine, self.engine.log().clone()) {
+                    Ok(replay) => self.replay = Some(replay),
+                    Err(error) => self.warning = Some(error.to_string()),
+                }
+            }
+        });
+
+        if let Some(dialog) = &mut self.stop_dialog {
+            let mut open = true;
+            // A walkover is recorded for the fighters entered on setup.
+            let walkover = RegulationState::None == self.engine.state();
+            let fighters = if walkover {
+                &self.fighters
+            } else {
+                self.engine.fighters()
+            };
+            if let Some(stop) = stop_window(ctx, &mut open, dialog, fighters) {
+                if walkover {
+                    self.set_up_match();
+                }
+                self.apply(stop);
+                open = false;
+            }
+            if !open {
+                self.stop_dialog = None;
+            }
+        }
+
+        if let Some(dialog) = &mut self.adjust_dialog {
+            let mut open = true;
+            if let Some(adjustment) = adjust_window(ctx, &mut open, dialog, &self.engine, now) {
+                self.engine.adjust(adjustment);
+            }
+            if !open {
+                self.adjust_dialog = None;
+            }
+        }
+
+        if let Some(replay) = &mut self.replay {
+            let mut open = true;
+            replay_window(ctx, &mut open, replay);
+            if !open {
+                self.replay = None;
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.style_mut().text_styles.insert(
                 egui::TextStyle::Button,
@@ -453,294 +1048,358 @@ impl eframe::App for CjjTimer {
                 egui::FontId::new(30.0, eframe::epaint::FontFamily::Proportional),
             );
             ui.heading("Hong Kong Combat Jiu Jitsu Timer");
-            let current_non_engaged_time = match self.state {
-                RegulationState::NotEngaged => {
-                    self.total_non_engaged_duration + self.start_non_engaged_instant.elapsed()
+            if let Some(warning) = self.warning.clone() {
+                ui.horizontal(|ui| {
+                    ui.colored_label(egui::Color32::YELLOW, warning);
+                    if ui.small_button("Dismiss").clicked() {
+                        self.warning = None;
+                    }
+                });
+            }
+            if self.resume_prompt {
+                ui.label("An interrupted match was found".to_string());
+                ui.label(format!("Match was: {:?}", self.engine.state()));
+                ui.label(format_time(
+                    "Current Time",
+                    self.engine.regulation_time(now),
+                ));
+                if ui.button("Resume").clicked() {
+                    self.resume_prompt = false;
                 }
-                _ => self.total_non_engaged_duration,
-            };
-            let match_stage = if (current_non_engaged_time >= Duration::from_secs(0))
-                && (current_non_engaged_time < self.half_penalty_free_duration)
-            {
-                MatchStage::FirstHalfPenaltyFree
-            } else if (current_non_engaged_time >= self.half_penalty_free_duration)
-                && (current_non_engaged_time < self.penalty_free_duration)
-            {
-                MatchStage::SecondHalfPenaltyFree
-            } else {
-                MatchStage::Penalty
-            };
-            match self.state {
-                RegulationState::Start => {
-                    self.change_regulation(Transition::Separate);
+                if ui.button("Discard").clicked() {
+                    self.engine = MatchEngine::default();
+                    self.resume_prompt = false;
                 }
-                RegulationState::NotEngaged => {
-                    if (self.total_regulation_duration + self.start_regulation_instant.elapsed())
-                        >= self.regulation_duration
-                    {
-                        self.change_regulation(Transition::TimeExpire);
+                return;
+            }
+            if RegulationState::None != self.engine.state() {
+                if let Some(change) = fighters_header(ui, &self.engine) {
+                    self.engine.score(change);
+                }
+                if self.engine.can_call_injury_timeout() {
+                    if let Some(fighter) = injury_timeout_buttons(ui, &self.engine, now) {
+                        self.apply(Transition::InjuryTimeout(fighter));
                     }
+                }
+                ui.separator();
+            }
+            if let Some(fighter) = self.engine.injury_timeout() {
+                if let Some(event) = injury_timeout_panel(ui, &self.engine, fighter, now) {
+                    self.apply(event);
+                }
+                return;
+            }
+            match self.engine.state() {
+                RegulationState::Start => {}
+                RegulationState::NotEngaged => {
                     ui.label("Fighters are NOT ENGAGED".to_string());
-                    ui.label(format_time("Match Time", self.regulation_duration));
+                    ui.label(format_time("Match Time", self.engine.regulation_duration()));
                     ui.label(format_time(
                         "Current Time",
-                        self.total_regulation_duration + self.start_regulation_instant.elapsed(),
+                        self.engine.regulation_time(now),
                     ));
-                    match match_stage {
-                        MatchStage::FirstHalfPenaltyFree => {
-                            ui.colored_label(
-                                egui::Color32::GREEN,
-                                format_time(
-                                    "1st Penalty Free Time",
-                                    self.total_non_engaged_duration
-                                        + self.start_non_engaged_instant.elapsed(),
-                                ),
-                            );
-                        }
-                        MatchStage::SecondHalfPenaltyFree => {
-                            ui.colored_label(
-                                egui::Color32::KHAKI,
-                                format_time(
-                                    "2nd Penalty Free Time",
-                                    self.total_non_engaged_duration
-                                        + self.start_non_engaged_instant.elapsed(),
-                                ),
-                            );
-                        }
-                        MatchStage::Penalty => {
-                            ui.colored_label(
-                                egui::Color32::RED,
-                                format_time(
-                                    "Penalty Time",
-                                    self.total_non_engaged_duration
-                                        + self.start_non_engaged_instant.elapsed()
-                                        - self.penalty_free_duration,
-                                ),
-                            );
-                        }
-                    };
+                    penalty_label(ui, &self.engine, now);
+                    if let Some(passivity) = passivity_selector(ui, &self.engine, now) {
+                        self.engine.attribute(passivity);
+                    }
                     if ui.button("Engaged").clicked() {
-                        self.change_regulation(Transition::Engage);
+                        self.apply(Transition::Engage);
                     }
 
                     ui.separator();
                     ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                         if ui.button("Pause").clicked() {
-                            self.change_regulation(Transition::Pause);
+                            self.apply(Transition::Pause);
                         }
                     });
                 }
                 RegulationState::Engaged => {
-                    if (self.total_regulation_duration + self.start_regulation_instant.elapsed())
-                        >= self.regulation_duration
-                    {
-                        self.change_regulation(Transition::TimeExpire);
-                    }
                     ui.label("Fighters are ENGAGED".to_string());
-                    ui.label(format_time("Match Time", self.regulation_duration));
+                    ui.label(format_time("Match Time", self.engine.regulation_duration()));
                     ui.label(format_time(
                         "Current Time",
-                        self.total_regulation_duration + self.start_regulation_instant.elapsed(),
+                        self.engine.regulation_time(now),
                     ));
-                    match match_stage {
-                        MatchStage::FirstHalfPenaltyFree => {
-                            ui.colored_label(
-                                egui::Color32::GREEN,
-                                format_time(
-                                    "1st Penalty Free Time",
-                                    self.total_non_engaged_duration,
-                                ),
-                            );
-                        }
-                        MatchStage::SecondHalfPenaltyFree => {
-                            ui.colored_label(
-                                egui::Color32::KHAKI,
-                                format_time(
-                                    "2nd Penalty Free Time",
-                                    self.total_non_engaged_duration,
-                                ),
-                            );
-                        }
-                        MatchStage::Penalty => {
-                            ui.colored_label(
-                                egui::Color32::RED,
-                                format_time(
-                                    "Penalty Time",
-                                    self.total_non_engaged_duration - self.penalty_free_duration,
-                                ),
-                            );
-                        }
-                    };
+                    penalty_label(ui, &self.engine, now);
                     if ui.button("Not Engaged").clicked() {
-                        self.change_regulation(Transition::Separate);
+                        self.apply(Transition::Separate);
                     }
                     ui.separator();
                     ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                         if ui.button("Pause").clicked() {
-                            self.change_regulation(Transition::Pause);
+                            self.apply(Transition::Pause);
                         }
                         if ui.button("Submission").clicked() {
-                            self.change_regulation(Transition::Submission);
+                            self.apply(Transition::Submission);
                         }
                     });
                 }
                 RegulationState::Paused => {
                     ui.label("Match is PAUSED".to_string());
                     if ui.button("Not Engaged").clicked() {
-                        self.change_regulation(Transition::Separate);
+                        self.apply(Transition::Separate);
                     }
                 }
                 RegulationState::Overtime => {
                     ui.label("Match is OVERTIME".to_string());
-                    match self.overtime_state {
+                    match self.engine.overtime_state() {
+                        OvertimeState::CoinToss => {
+                            let fighters = self.engine.fighters();
+                            ui.label("Coin toss for overtime");
+                            ui.horizontal(|ui| {
+                                ui.label("Won by:");
+                                for fighter in [Fighter::A, Fighter::B] {
+                                    ui.radio_value(
+                                        &mut self.toss.chooser,
+                                        fighter,
+                                        fighters.name(fighter),
+                                    );
+                                }
+                                if ui.button("Random draw").clicked() {
+                                    self.toss.chooser = draw_fighter();
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Chooses to:");
+                                ui.radio_value(&mut self.toss.position, Position::Attack, "Attack");
+                                ui.radio_value(&mut self.toss.position, Position::Defend, "Defend");
+                            });
+                            ui.label(format!(
+                                "First round offensive is {}",
+                                fighters.name(self.toss.first_attacker())
+                            ));
+                            if ui.button("Start Overtime").clicked() {
+                                self.apply(Transition::CoinToss(self.toss));
+                            }
+                        }
                         OvertimeState::AdvanceOvertime => {
-                            ui.label("Advance Overtime Round");
-                            ui.label(format!("Escape Time: {:?}", self.overtime_segments));
+                            ui.label(format!(
+                                "Advance Overtime Round {} of {}",
+                                self.engine.overtime_segments().len() + 1,
+                                self.engine.rules().overtime_rounds
+                            ));
+                            let attacker = self
+                                .engine
+                                .segment_fighter(self.engine.overtime_segments().len());
+                            ui.label(format!("{} attacks", self.engine.fighters().name(attacker)));
+                            segment_list(ui, &self.engine);
                             if ui.button("Start Round").clicked() {
-                                self.total_overtime_duration = Duration::from_secs(0);
-                                self.start_overtime_instant = Instant::now();
-                                self.change_overtime(Transition::Engage);
+                                self.apply(Transition::Engage);
                             }
                         }
                         OvertimeState::Engaged => {
                             ui.label("Fighters are Engaged");
-                            let current_ot = self.total_overtime_duration
-                                + self.start_overtime_instant.elapsed();
-                            let calculated_ot =
-                                self.penalty_time_divided + self.standard_overtime_duration;
-                            ui.label(format_time("Segment Time", calculated_ot));
-                            ui.label(format_time("Current Time", current_ot));
-                            if current_ot >= calculated_ot {
-                                self.overtime_segments.push(Segment::Escape(calculated_ot));
-                                if self.calculate_win() {
-                                    self.change_overtime(Transition::Win);
-                                } else {
-                                    self.change_overtime(Transition::TimeExpire);
-                                }
-                            }
+                            ui.label(format_time("Segment Time", self.engine.segment_duration()));
+                            ui.label(format_time("Current Time", self.engine.overtime_time(now)));
                             if ui.button("Escape").clicked() {
                                 // addressing = 2 * element + segment
-                                self.overtime_segments.push(Segment::Escape(
-                                    self.total_overtime_duration
-                                        + self.start_overtime_instant.elapsed(),
-                                ));
-                                if self.calculate_win() {
-                                    self.change_overtime(Transition::Win);
-                                } else {
-                                    self.change_overtime(Transition::Separate);
-                                }
+                                self.apply(Transition::Separate);
                             }
                             if ui.button("Submission").clicked() {
-                                self.overtime_segments.push(Segment::Submission(
-                                    self.total_overtime_duration
-                                        + self.start_overtime_instant.elapsed(),
-                                ));
-                                if self.calculate_win() {
-                                    self.change_overtime(Transition::Win);
-                                } else {
-                                    self.change_overtime(Transition::Submission);
-                                }
+                                self.apply(Transition::Submission);
                             }
                             ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                                 if ui.button("Pause").clicked() {
-                                    self.change_overtime(Transition::Pause);
+                                    self.apply(Transition::Pause);
                                 }
                             });
                         }
                         OvertimeState::Escaped => {
                             ui.label("Fighters Escaped");
-                            ui.label(format!("Rounds: {:?}", self.overtime_segments));
+                            segment_list(ui, &self.engine);
                             if ui.button("Undo then Engage").clicked() {
-                                self.change_overtime(Transition::Undo);
+                                self.apply(Transition::Undo);
                             }
                             if ui.button("Advance Round").clicked() {
-                                self.change_overtime(Transition::TimeExpire);
+                                self.apply(Transition::TimeExpire);
                             }
                         }
                         OvertimeState::Submission => {
                             ui.label("Fighter Submission");
-                            ui.label(format!("Rounds: {:?}", self.overtime_segments));
+                            segment_list(ui, &self.engine);
                             if ui.button("Undo then Engage").clicked() {
-                                self.change_overtime(Transition::Undo);
+                                self.apply(Transition::Undo);
                             }
                             if ui.button("Advance Round").clicked() {
-                                self.change_overtime(Transition::TimeExpire);
+                                self.apply(Transition::TimeExpire);
                             }
                         }
                         OvertimeState::Paused => {
                             ui.label("Match is in Overtime Paused");
                             if ui.button("Engage").clicked() {
-                                self.change_overtime(Transition::Engage);
+                                self.apply(Transition::Engage);
                             }
                         }
                         OvertimeState::Win => {
-                            ui.label("First round offensive is A".to_string());
-                            ui.label("First round defensive is B".to_string());
+                            let fighters = self.engine.fighters();
+                            let first = self.engine.first_attacker();
+                            ui.label(format!("First round offensive is {}", fighters.name(first)));
+                            ui.label(format!(
+                                "First round defensive is {}",
+                                fighters.name(first.other())
+                            ));
                             ui.separator();
                             if ui.button("Undo then Engage").clicked() {
-                                self.change_overtime(Transition::Undo);
+                                self.apply(Transition::Undo);
                             }
                             ui.separator();
-                            ui.label(format!(
-                                "The Winner is: {:?}",
-                                self.winner.as_ref().unwrap()
-                            ));
-                            for (i, segment) in self.overtime_segments.iter().enumerate() {
-                                let fighter = if i % 2 == 0 { Fighter::A } else { Fighter::B };
-                                ui.label(format!("{:?}{}: {:?}", fighter, i, segment));
+                            let winner = self.engine.winner().unwrap();
+                            ui.horizontal(|ui| {
+                                corner_marker(ui, self.engine.fighters().get(winner).corner);
+                                ui.label(format!(
+                                    "The Winner is: {}",
+                                    self.engine.fighters().name(winner)
+                                ));
+                            });
+                            tiebreak_label(ui, &self.engine);
+                            segment_list(ui, &self.engine);
+                            if ui.button("New Match").clicked() {
+                                self.engine = MatchEngine::default();
+                            }
+                        }
+                        OvertimeState::Draw => {
+                            if ui.button("Undo then Engage").clicked() {
+                                self.apply(Transition::Undo);
+                            }
+                            ui.separator();
+                            ui.label("The match is a DRAW");
+                            tiebreak_label(ui, &self.engine);
+                            segment_list(ui, &self.engine);
+                            if ui.button("New Match").clicked() {
+                                self.engine = MatchEngine::default();
                             }
                         }
+                        OvertimeState::Tied => {
+                            ui.label("Escapes are level, waiting for the referee's decision");
+                            for fighter in [Fighter::A, Fighter::B] {
+                                let name = self.engine.fighters().name(fighter);
+                                if ui.button(format!("{} wins by decision", name)).clicked() {
+                                    self.apply(Transition::Decision(fighter));
+                                }
+                            }
+                            if ui.button("Undo then Engage").clicked() {
+                                self.apply(Transition::Undo);
+                            }
+                            ui.separator();
+                            tiebreak_label(ui, &self.engine);
+                            segment_list(ui, &self.engine);
+                        }
                     };
                 }
                 RegulationState::Restarted => {
                     ui.label("Match is RESTARTED".to_string());
                     if ui.button("Start").clicked() {
-                        self.change_regulation(Transition::StartRegulation);
+                        self.apply(Transition::StartRegulation);
                     }
                 }
                 RegulationState::Submission => {
                     ui.label("Match ended in SUBMISSION".to_string());
+                    if self.engine.result().is_some() {
+                        result_label(ui, &self.engine);
+                    } else if let Some(submitted) =
+                        submission_form(ui, &mut self.submission, &self.engine)
+                    {
+                        self.apply(submitted);
+                    }
+                    ui.separator();
                     if ui.button("Undo").clicked() {
-                        self.change_regulation(Transition::Undo);
+                        self.apply(Transition::Undo);
                     }
                     if ui.button("Restart").clicked() {
-                        self.change_regulation(Transition::Restart);
+                        self.apply(Transition::Restart);
+                    }
+                }
+                RegulationState::Decided => {
+                    ui.label("Match DECIDED on the scores".to_string());
+                    result_label(ui, &self.engine);
+                    if ui.button("New Match").clicked() {
+                        self.engine = MatchEngine::default();
+                    }
+                }
+                RegulationState::Stopped => {
+                    ui.label("Match STOPPED by the referee".to_string());
+                    result_label(ui, &self.engine);
+                    if ui.button("New Match").clicked() {
+                        self.engine = MatchEngine::default();
                     }
                 }
                 RegulationState::None => {
+                    ui.columns(2, |columns| {
+                        for (ui, fighter) in columns.iter_mut().zip([Fighter::A, Fighter::B]) {
+                            ui.label(format!("Fighter {:?}", fighter));
+                            fighter_form(ui, fighter, self.fighters.get_mut(fighter));
+                        }
+                    });
+                    ui.separator();
+                    profile_selector(ui, &mut self.rules);
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if let Err(error) = rule_file_buttons(ui, &mut self.rules, &mut self.rules_path)
+                    {
+                        self.warning = Some(error.to_string());
+                    }
                     ui.label("Regulation Match Time as seconds:".to_string());
-                    integer_edit_field(
-                        ui,
-                        &mut self.regulation_input,
-                        &mut self.regulation_duration,
-                    );
+                    integer_edit_field(ui, &mut self.rules.regulation);
                     ui.label("Penalty Free Duration as seconds:".to_string());
-                    integer_edit_field(
-                        ui,
-                        &mut self.penalty_free_input,
-                        &mut self.penalty_free_duration,
-                    );
+                    integer_edit_field(ui, &mut self.rules.penalty_free);
+                    ui.label("First Half Penalty Free Duration as seconds:".to_string());
+                    integer_edit_field(ui, &mut self.rules.penalty_free_split);
                     ui.label("Standard Overtime Duration as seconds:".to_string());
-                    integer_edit_field(
-                        ui,
-                        &mut self.standard_overtime_input,
-                        &mut self.standard_overtime_duration,
+                    integer_edit_field(ui, &mut self.rules.standard_overtime);
+                    ui.label("Overtime Rounds:".to_string());
+                    ui.add(egui::DragValue::new(&mut self.rules.overtime_rounds));
+                    ui.label("Injury Time per Fighter as seconds:".to_string());
+                    integer_edit_field(ui, &mut self.rules.injury_allowance);
+                    ui.checkbox(
+                        &mut self.rules.scoring,
+                        "Score points, advantages and penalties",
+                    );
+                    ui.checkbox(
+                        &mut self.rules.stalling_penalty,
+                        "Penalise each fighter for their own stalling",
                     );
+                    egui::ComboBox::from_label("Tiebreak")
+                        .selected_text(self.rules.tiebreak.label())
+                        .show_ui(ui, |ui| {
+                            for policy in TiebreakPolicy::ALL {
+                                ui.selectable_value(
+                                    &mut self.rules.tiebreak,
+                                    policy,
+                                    policy.label(),
+                                );
+                            }
+                        });
                     ui.separator();
+                    ui.label(format_time("Regulation Match Time", self.rules.regulation));
                     ui.label(format_time(
-                        "Regulation Match Time",
-                        self.regulation_duration,
+                        "Penalty Free Duration",
+                        self.rules.penalty_free,
                     ));
                     ui.label(format_time(
-                        "Penalty Free Duration",
-                        self.penalty_free_duration,
+                        "First Half Penalty Free Duration",
+                        self.rules.penalty_free_split,
                     ));
                     ui.label(format_time(
                         "Standard Overtime Duration",
-                        self.standard_overtime_duration,
+                        self.rules.standard_overtime,
                     ));
-                    if ui.button("Start").clicked() {
-                        self.change_regulation(Transition::StartRegulation);
+                    ui.label(format!("Overtime Rounds: {}", self.rules.overtime_rounds));
+                    ui.label(format_time(
+                        "Injury Time per Fighter",
+                        self.rules.injury_allowance,
+                    ));
+                    let settings_error = self.rules.validate().err();
+                    if let Some(error) = &settings_error {
+                        ui.colored_label(egui::Color32::RED, error.to_string());
+                    }
+                    if ui.button("Reset to defaults").clicked() {
+                        self.rules = RuleSet::default();
+                    }
+                    if ui
+                        .add_enabled(settings_error.is_none(), egui::Button::new("Start"))
+                        .clicked()
+                    {
+                        self.set_up_match();
+                        self.apply(Transition::StartRegulation);
                     }
                 }
             }
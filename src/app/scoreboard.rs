@@ -0,0 +1,217 @@
+use web_time::{Duration, Instant};
+
+use super::{corner_colour, stage_colour};
+use crate::engine::{Fighter, MatchEngine, OvertimeState, RegulationState, Segment};
+use crate::live::{clock_text, seconds_left};
+
+const CLOCK_SIZE: f32 = 180.0;
+const NAME_SIZE: f32 = 64.0;
+const TEXT_SIZE: f32 = 40.0;
+
+/// Show the audience scoreboard for `engine` in its own native window.
+///
+/// F11 or a double click toggles fullscreen, Escape leaves it. Returns `false` once
+/// the window is closed.
+pub(super) fn show(ctx: &egui::Context, engine: &MatchEngine, now: Instant) -> bool {
+    let mut open = true;
+    ctx.show_viewport_immediate(
+        egui::ViewportId::from_hash_of("scoreboard"),
+        egui::ViewportBuilder::default()
+            .with_title("CJJ Scoreboard")
+            .with_inner_size([1280.0, 720.0]),
+        |ctx, _class| {
+            let (toggle, leave, fullscreen) = ctx.input(|i| {
+                (
+                    i.key_pressed(egui::Key::F11)
+                        || i.pointer
+                            .button_double_clicked(egui::PointerButton::Primary),
+                    i.key_pressed(egui::Key::Escape),
+                    i.viewport().fullscreen.unwrap_or(false),
+                )
+            });
+            if toggle || (leave && fullscreen) {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(!fullscreen));
+            }
+            egui::CentralPanel::default().show(ctx, |ui| scoreboard(ui, engine, now));
+            if ctx.input(|i| i.viewport().close_requested()) {
+                open = false;
+            }
+        },
+    );
+    open
+}
+
+/// A clock counting up.
+fn time_text(time: Duration) -> String {
+    clock_text(time.as_secs())
+}
+
+/// A clock counting down, the same way the network scoreboard and overlay show it.
+fn left_text(left: Duration) -> String {
+    clock_text(seconds_left(left))
+}
+
+fn big(text: impl Into<String>, size: f32) -> egui::RichText {
+    egui::RichText::new(text).size(size).strong()
+}
+
+fn scoreboard(ui: &mut egui::Ui, engine: &MatchEngine, now: Instant) {
+    ui.columns(2, |columns| {
+        for (ui, fighter) in columns.iter_mut().zip([Fighter::A, Fighter::B]) {
+            fighter_card(ui, engine, fighter);
+        }
+    });
+    ui.separator();
+    ui.vertical_centered(|ui| {
+        if let Some(fighter) = engine.injury_timeout() {
+            ui.label(big(
+                format!("INJURY TIMEOUT {}", engine.fighters().name(fighter)),
+                TEXT_SIZE,
+            ));
+            let colour = if engine.injury_allowance_exhausted(fighter, now) {
+                egui::Color32::RED
+            } else {
+                ui.visuals().strong_text_color()
+            };
+            ui.label(
+                big(left_text(engine.injury_time_left(fighter, now)), CLOCK_SIZE).color(colour),
+            );
+            return;
+        }
+        match engine.state() {
+            RegulationState::None => {
+                ui.label(big("Waiting for the next match", TEXT_SIZE));
+            }
+            RegulationState::Overtime => overtime(ui, engine, now),
+            state => regulation(ui, engine, state, now),
+        }
+        if let Some(winner) = engine.winner() {
+            let mut text = format!("WINNER {}", engine.fighters().name(winner));
+            if let Some(result) = engine.result() {
+                text.push_str(" by ");
+                text.push_str(result.method.label(engine.rules()));
+            }
+            ui.label(
+                big(text, NAME_SIZE).color(corner_colour(engine.fighters().get(winner).corner)),
+            );
+        } else if OvertimeState::Draw == engine.overtime_state()
+            && RegulationState::Overtime == engine.state()
+        {
+            ui.label(big("DRAW", NAME_SIZE));
+        }
+    });
+}
+
+/// A fighter's name in their corner colour, with their team and, in a points division,
+/// their scores.
+fn fighter_card(ui: &mut egui::Ui, engine: &MatchEngine, fighter: Fighter) {
+    let details = engine.fighters().get(fighter);
+    ui.vertical_centered(|ui| {
+        ui.label(
+            big(engine.fighters().name(fighter), NAME_SIZE).color(corner_colour(details.corner)),
+        );
+        if !details.team.trim().is_empty() {
+            ui.label(big(details.team.trim(), TEXT_SIZE));
+        }
+        if engine.rules().scoring {
+            let score = engine.scores().get(fighter);
+            ui.label(big(
+                format!(
+                    "{} pts   {} adv   {} pen",
+                    score.points, score.advantages, score.penalties
+                ),
+                NAME_SIZE,
+            ));
+        }
+    });
+}
+
+/// Regulation time left, with the non engaged time in the colour of its penalty stage.
+fn regulation(ui: &mut egui::Ui, engine: &MatchEngine, state: RegulationState, now: Instant) {
+    let status = match state {
+        RegulationState::Engaged => "ENGAGED",
+        RegulationState::NotEngaged => "NOT ENGAGED",
+        RegulationState::Paused => "PAUSED",
+        RegulationState::Submission => "SUBMISSION",
+        RegulationState::Decided => "DECIDED",
+        RegulationState::Stopped => "STOPPED",
+        _ => "",
+    };
+    ui.label(big(status, TEXT_SIZE));
+    let left = engine
+        .regulation_duration()
+        .saturating_sub(engine.regulation_time(now));
+    ui.label(big(left_text(left), CLOCK_SIZE));
+    ui.label(
+        big(
+            format!("Non Engaged {}", time_text(engine.non_engaged_time(now))),
+            NAME_SIZE,
+        )
+        .color(stage_colour(engine.match_stage(now))),
+    );
+}
+
+/// The running segment and a table of every segment so far.
+fn overtime(ui: &mut egui::Ui, engine: &MatchEngine, now: Instant) {
+    let fighters = engine.fighters();
+    let rounds = engine.rules().overtime_rounds;
+    let played = engine.overtime_segments().len();
+    match engine.overtime_state() {
+        OvertimeState::CoinToss => {
+            ui.label(big("OVERTIME", NAME_SIZE));
+        }
+        OvertimeState::Win | OvertimeState::Draw | OvertimeState::Tied => {
+            ui.label(big("OVERTIME OVER", NAME_SIZE));
+        }
+        OvertimeState::AdvanceOvertime | OvertimeState::Engaged | OvertimeState::Paused => {
+            let attacker = engine.segment_fighter(played);
+            ui.label(big(
+                format!(
+                    "OVERTIME {} of {}: {} attacks",
+                    played + 1,
+                    rounds,
+                    fighters.name(attacker)
+                ),
+                TEXT_SIZE,
+            ));
+            let left = engine
+                .segment_duration()
+                .saturating_sub(engine.overtime_time(now));
+            ui.label(big(left_text(left), CLOCK_SIZE));
+        }
+        OvertimeState::Escaped | OvertimeState::Submission => {
+            ui.label(big(time_text(engine.overtime_time(now)), CLOCK_SIZE));
+        }
+    }
+    if played == 0 {
+        return;
+    }
+    let first = engine.first_attacker();
+    egui::Grid::new("scoreboard_segments")
+        .num_columns(3)
+        .spacing([80.0, 8.0])
+        .show(ui, |ui| {
+            ui.label(big("Round", TEXT_SIZE));
+            for fighter in [first, first.other()] {
+                ui.label(
+                    big(fighters.name(fighter), TEXT_SIZE)
+                        .color(corner_colour(fighters.get(fighter).corner)),
+                );
+            }
+            ui.end_row();
+            for (round, pair) in engine.overtime_segments().chunks(2).enumerate() {
+                ui.label(big((round + 1).to_string(), TEXT_SIZE));
+                for segment in pair {
+                    ui.label(big(segment_text(*segment), TEXT_SIZE));
+                }
+                ui.end_row();
+            }
+        });
+}
+
+fn segment_text(segment: Segment) -> String {
+    match segment {
+        Segment::Escape(time) => time_text(time),
+        Segment::Submission(time) => format!("SUB {}", time_text(time)),
+    }
+}
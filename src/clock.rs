@@ -0,0 +1,76 @@
+use std::sync::{Arc, Mutex};
+
+use web_time::{Duration, Instant, SystemTime};
+
+/// Where the match engine gets the current time from.
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, only used to label what happened when.
+    fn system_time(&self) -> SystemTime;
+}
+
+/// The real time, as used by the app.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to, for tests and scripted matches.
+///
+/// Clones share the same time, so keep one to move the clock handed to an engine.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    start_system_time: SystemTime,
+    offset: Arc<Mutex<Duration>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            start_system_time: SystemTime::now(),
+            offset: Arc::new(Mutex::new(Duration::from_secs(0))),
+        }
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.offset.lock().unwrap() += duration;
+    }
+
+    /// Set the clock to `offset` after the moment it was created.
+    pub fn set(&self, offset: Duration) {
+        *self.offset.lock().unwrap() = offset;
+    }
+
+    /// How far the clock has moved since it was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.offset.lock().unwrap()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.start_system_time + self.elapsed()
+    }
+}
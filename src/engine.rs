@@ -0,0 +1,1348 @@
+use std::sync::Arc;
+
+use web_time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::event_log::{EventLog, LogEntry, MatchEvent};
+use crate::fighters::Fighters;
+use crate::rules::{RuleSet, TiebreakPolicy};
+use crate::scoring::{ScoreChange, Scores};
+use crate::stalling::{FighterTimes, Passivity};
+
+mod saved;
+pub use saved::SavedMatch;
+
+/// How much of the penalty free time the fighters have used up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum MatchStage {
+    FirstHalfPenaltyFree,
+    SecondHalfPenaltyFree,
+    Penalty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub enum Fighter {
+    A,
+    B,
+}
+
+impl Fighter {
+    /// The opponent.
+    pub fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+}
+
+/// Where a fighter starts an overtime round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Position {
+    Attack,
+    Defend,
+}
+
+/// Who won the coin toss before overtime, and where they chose to start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct CoinToss {
+    pub chooser: Fighter,
+    pub position: Position,
+}
+
+impl CoinToss {
+    /// The fighter attacking in the first overtime round.
+    pub fn first_attacker(&self) -> Fighter {
+        match self.position {
+            Position::Attack => self.chooser,
+            Position::Defend => self.chooser.other(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Segment {
+    Escape(Duration),
+    Submission(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum RegulationState {
+    Start,
+    Restarted,
+    NotEngaged,
+    Paused,
+    Engaged,
+    Overtime,
+    Submission,
+    /// Regulation ran out and the scores decided the match.
+    Decided,
+    /// The referee ended the match early.
+    Stopped,
+    None,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Transition {
+    StartRegulation,
+    StartOvertime,
+    Restart,
+    Pause,
+    Engage,
+    Separate,
+    TimeExpire,
+    Submission,
+    Undo,
+    Win,
+    /// Overtime ended level and the rules call it a draw.
+    Draw,
+    /// Overtime ended level and the referee has to decide.
+    Tie,
+    /// The referee's decision on a tied overtime.
+    Decision(Fighter),
+    /// The result of the coin toss that starts overtime.
+    CoinToss(CoinToss),
+    /// The referee ends the match early.
+    Stop {
+        winner: Fighter,
+        reason: Stoppage,
+    },
+    /// Who won a regulation submission, with the technique at that index in
+    /// [`RuleSet::submissions`].
+    Submitted {
+        winner: Fighter,
+        technique: usize,
+    },
+    /// Stop the match for a fighter's injury and start their injury clock.
+    InjuryTimeout(Fighter),
+    /// Stop the injury clock. The match stays paused until it is restarted as usual.
+    EndInjuryTimeout,
+}
+
+#[derive(Debug, Copy, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize)]
+pub enum OvertimeState {
+    /// Waiting for the coin toss that decides who attacks first.
+    CoinToss,
+    Paused,
+    Engaged,
+    Escaped,
+    Submission,
+    AdvanceOvertime,
+    Win,
+    Draw,
+    /// Waiting for the referee to decide a level overtime.
+    Tied,
+}
+
+/// Why the referee ended a match early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Stoppage {
+    Disqualification,
+    /// A walkover, when the opponent doesn't show or withdraws.
+    Forfeit,
+    Injury,
+    Doctor,
+    RefereeDecision,
+}
+
+impl Stoppage {
+    pub const ALL: [Self; 5] = [
+        Self::Disqualification,
+        Self::Forfeit,
+        Self::Injury,
+        Self::Doctor,
+        Self::RefereeDecision,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Disqualification => "Disqualification",
+            Self::Forfeit => "Forfeit",
+            Self::Injury => "Injury stoppage",
+            Self::Doctor => "Doctor stoppage",
+            Self::RefereeDecision => "Referee decision",
+        }
+    }
+}
+
+/// How a match was won, other than in overtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum WinMethod {
+    Points,
+    Advantages,
+    Penalties,
+    Stoppage(Stoppage),
+    /// With the technique at this index in [`RuleSet::submissions`].
+    Submission {
+        technique: usize,
+    },
+}
+
+impl WinMethod {
+    /// The name of the method, looking submission techniques up in `rules`.
+    pub fn label(self, rules: &RuleSet) -> &str {
+        match self {
+            Self::Points => "Points",
+            Self::Advantages => "Advantages",
+            Self::Penalties => "Penalties",
+            Self::Stoppage(reason) => reason.label(),
+            Self::Submission { technique } => rules
+                .submissions
+                .get(technique)
+                .map_or("Submission", String::as_str),
+        }
+    }
+}
+
+/// The outcome of a match that was decided in regulation or stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct MatchResult {
+    pub winner: Fighter,
+    pub method: WinMethod,
+    /// Time on the running clock when it ended: regulation time, or the segment time in overtime.
+    /// Zero if it ended before that clock started: a walkover, or a stop between segments.
+    pub time: Duration,
+}
+
+/// The clocks officials can correct by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum MatchClock {
+    Regulation,
+    NonEngaged,
+    Overtime,
+}
+
+/// Time added to (or, when negative, removed from) one of the clocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Adjustment {
+    pub clock: MatchClock,
+    pub seconds: i64,
+}
+
+/// The regulation and overtime state a match is in after an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct MatchState {
+    pub regulation: RegulationState,
+    pub overtime: OvertimeState,
+}
+
+/// An event that is not allowed in the state the match is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionError {
+    Regulation {
+        state: RegulationState,
+        event: Transition,
+    },
+    Overtime {
+        state: OvertimeState,
+        event: Transition,
+    },
+}
+
+impl std::fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Regulation { state, event } => {
+                write!(
+                    f,
+                    "{:?} is not allowed while regulation is {:?}",
+                    event, state
+                )
+            }
+            Self::Overtime { state, event } => {
+                write!(
+                    f,
+                    "{:?} is not allowed while overtime is {:?}",
+                    event, state
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+/// The match rules, free of any UI.
+///
+/// Events go in through [`MatchEngine::apply`], and [`MatchEngine::tick`] must be
+/// called regularly so that regulation and overtime segments expire on time.
+/// All time is read from the engine's [`Clock`].
+///
+/// It is serialized as a [`SavedMatch`], so a match survives the app being restarted.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(into = "SavedMatch", from = "SavedMatch")]
+pub struct MatchEngine {
+    clock: Arc<dyn Clock>,
+    epoch: Instant,
+    /// Match time that had already passed at `epoch`, when the match was resumed.
+    epoch_offset: Duration,
+    log: EventLog,
+    undo_stack: Vec<MatchEngine>,
+    redo_stack: Vec<MatchEngine>,
+    start_non_engaged_instant: Instant,
+    total_non_engaged_duration: Duration,
+    start_regulation_instant: Instant,
+    total_regulation_duration: Duration,
+    total_overtime_duration: Duration,
+    start_overtime_instant: Instant,
+    rules: RuleSet,
+    fighters: Fighters,
+    total_penalty_duration: Duration,
+    penalty_time_divided: Duration,
+    /// Who is held responsible for the running non engaged period.
+    passivity: Passivity,
+    /// Non engaged time each fighter was held responsible for.
+    stalling: FighterTimes,
+    fighter_penalty_divided: FighterTimes,
+    /// The fighter whose injury clock is running.
+    injury_timeout: Option<Fighter>,
+    start_injury_instant: Instant,
+    /// Injury time each fighter has used, not counting the running timeout.
+    injury: FighterTimes,
+    state: RegulationState,
+    overtime_state: OvertimeState,
+    overtime_segments: Vec<Segment>,
+    toss: Option<CoinToss>,
+    scores: Scores,
+    winner: Option<Fighter>,
+    result: Option<MatchResult>,
+}
+
+impl Default for MatchEngine {
+    fn default() -> Self {
+        Self::new(RuleSet::default())
+    }
+}
+
+impl MatchEngine {
+    pub fn new(rules: RuleSet) -> Self {
+        let clock = SystemClock;
+        let now = clock.now();
+        Self {
+            clock: Arc::new(clock),
+            epoch: now,
+            epoch_offset: Duration::from_secs(0),
+            log: EventLog::default(),
+            undo_stack: vec![],
+            redo_stack: vec![],
+            start_non_engaged_instant: now,
+            total_non_engaged_duration: Duration::from_secs(0),
+            start_regulation_instant: now,
+            total_regulation_duration: Duration::from_secs(0),
+            total_overtime_duration: Duration::from_secs(0),
+            start_overtime_instant: now,
+            rules,
+            fighters: Fighters::default(),
+            total_penalty_duration: Duration::from_secs(0),
+            penalty_time_divided: Duration::from_secs(0),
+            passivity: Passivity::Both,
+            stalling: FighterTimes::default(),
+            fighter_penalty_divided: FighterTimes::default(),
+            injury_timeout: None,
+            start_injury_instant: now,
+            injury: FighterTimes::default(),
+            state: RegulationState::None,
+            overtime_state: OvertimeState::AdvanceOvertime,
+            overtime_segments: vec![],
+            toss: None,
+            scores: Scores::default(),
+            winner: None,
+            result: None,
+        }
+    }
+
+    /// Replace the system clock, e.g. with a [`crate::ManualClock`] in tests.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        let now = clock.now();
+        self.epoch = now;
+        self.start_non_engaged_instant = now;
+        self.start_regulation_instant = now;
+        self.start_overtime_instant = now;
+        self.start_injury_instant = now;
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Set who is fighting.
+    pub fn with_fighters(mut self, fighters: Fighters) -> Self {
+        self.fighters = fighters;
+        self
+    }
+
+    /// The current time according to the engine's clock.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Feed an event (usually a button press) into the match.
+    ///
+    /// An event that makes no sense in the current state is rejected and the
+    /// match is left as it was.
+    pub fn apply(&mut self, event: Transition) -> Result<MatchState, TransitionError> {
+        let now = self.clock.now();
+        let snapshot = self.snapshot();
+        if let Err(error) = self.change(event, now) {
+            log::warn!("Rejected transition: {}", error);
+            self.restore(snapshot);
+            return Err(error);
+        }
+        self.undo_stack.push(snapshot);
+        self.redo_stack.clear();
+        self.record(MatchEvent::Transition(event), now);
+        Ok(self.match_state())
+    }
+
+    /// Put the match back to how it was before the last applied transition.
+    ///
+    /// The clocks keep the time that has passed since, as if the transition never happened.
+    /// Returns `None` if there is nothing to undo.
+    pub fn undo(&mut self) -> Option<MatchState> {
+        let snapshot = self.undo_stack.pop()?;
+        let current = self.snapshot();
+        self.restore(snapshot);
+        self.redo_stack.push(current);
+        self.record(MatchEvent::Undo, self.clock.now());
+        Some(self.match_state())
+    }
+
+    /// Take back the last [`MatchEngine::undo`]. Returns `None` if there is nothing to redo.
+    pub fn redo(&mut self) -> Option<MatchState> {
+        let snapshot = self.redo_stack.pop()?;
+        let current = self.snapshot();
+        self.restore(snapshot);
+        self.undo_stack.push(current);
+        self.record(MatchEvent::Redo, self.clock.now());
+        Some(self.match_state())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Apply a logged event, as when replaying a match.
+    pub fn apply_event(&mut self, event: MatchEvent) -> Result<MatchState, TransitionError> {
+        match event {
+            MatchEvent::Transition(transition) => self.apply(transition),
+            MatchEvent::Undo => Ok(self.undo().unwrap_or_else(|| self.match_state())),
+            MatchEvent::Redo => Ok(self.redo().unwrap_or_else(|| self.match_state())),
+            MatchEvent::Adjust(adjustment) => Ok(self.adjust(adjustment)),
+            MatchEvent::Score(change) => Ok(self.score(change)),
+            MatchEvent::Attribute(passivity) => Ok(self.attribute(passivity)),
+        }
+    }
+
+    /// Add or remove time on one of the clocks, as told by the referee.
+    ///
+    /// Non engaged time is also added to or taken from the stalling time of whoever
+    /// is held responsible for it. Once in overtime, the penalty split is worked out
+    /// again from the adjusted non engaged time.
+    pub fn adjust(&mut self, adjustment: Adjustment) -> MatchState {
+        let now = self.clock.now();
+        self.undo_stack.push(self.snapshot());
+        self.redo_stack.clear();
+        // Bank the running period first, so that time can be taken off it as well.
+        match (adjustment.clock, self.state, self.overtime_state) {
+            (MatchClock::Regulation, RegulationState::NotEngaged | RegulationState::Engaged, _) => {
+                self.total_regulation_duration = self.regulation_time(now);
+                self.start_regulation_instant = now;
+            }
+            (MatchClock::NonEngaged, RegulationState::NotEngaged, _) => {
+                self.count_non_engaged(now);
+                self.start_non_engaged_instant = now;
+            }
+            (MatchClock::Overtime, RegulationState::Overtime, OvertimeState::Engaged) => {
+                self.total_overtime_duration = self.overtime_time(now);
+                self.start_overtime_instant = now;
+            }
+            _ => {}
+        }
+        let total = match adjustment.clock {
+            MatchClock::Regulation => &mut self.total_regulation_duration,
+            MatchClock::NonEngaged => &mut self.total_non_engaged_duration,
+            MatchClock::Overtime => &mut self.total_overtime_duration,
+        };
+        let amount = Duration::from_secs(adjustment.seconds.unsigned_abs());
+        *total = if adjustment.seconds < 0 {
+            total.saturating_sub(amount)
+        } else {
+            *total + amount
+        };
+        if MatchClock::NonEngaged == adjustment.clock {
+            if adjustment.seconds < 0 {
+                self.stalling.remove(self.passivity, amount);
+            } else {
+                self.stalling.add(self.passivity, amount);
+            }
+        }
+        if RegulationState::Overtime == self.state {
+            self.calculate_penalty();
+        }
+        self.record(MatchEvent::Adjust(adjustment), now);
+        self.match_state()
+    }
+
+    /// Give out or take back points, advantages or penalties.
+    pub fn score(&mut self, change: ScoreChange) -> MatchState {
+        let now = self.clock.now();
+        self.undo_stack.push(self.snapshot());
+        self.redo_stack.clear();
+        self.scores.change(change);
+        self.record(MatchEvent::Score(change), now);
+        self.match_state()
+    }
+
+    /// Hold `passivity` responsible for the non engaged time from now on, until the
+    /// fighters engage again.
+    pub fn attribute(&mut self, passivity: Passivity) -> MatchState {
+        let now = self.clock.now();
+        self.undo_stack.push(self.snapshot());
+        self.redo_stack.clear();
+        if RegulationState::NotEngaged == self.state {
+            self.count_non_engaged(now);
+            self.start_non_engaged_instant = now;
+        }
+        self.passivity = passivity;
+        self.record(MatchEvent::Attribute(passivity), now);
+        self.match_state()
+    }
+
+    /// Count the running non engaged period up to `now`, for the match and the fighters
+    /// held responsible.
+    fn count_non_engaged(&mut self, now: Instant) {
+        let period = now - self.start_non_engaged_instant;
+        self.total_non_engaged_duration += period;
+        self.stalling.add(self.passivity, period);
+    }
+
+    /// A copy of the match without its log and undo history.
+    fn snapshot(&self) -> MatchEngine {
+        MatchEngine {
+            clock: self.clock.clone(),
+            log: EventLog::default(),
+            undo_stack: vec![],
+            redo_stack: vec![],
+            rules: self.rules.clone(),
+            fighters: self.fighters.clone(),
+            overtime_segments: self.overtime_segments.clone(),
+            ..*self
+        }
+    }
+
+    /// Go back to `snapshot`, keeping the log and undo history.
+    fn restore(&mut self, snapshot: MatchEngine) {
+        let log = std::mem::take(&mut self.log);
+        let undo_stack = std::mem::take(&mut self.undo_stack);
+        let redo_stack = std::mem::take(&mut self.redo_stack);
+        *self = MatchEngine {
+            log,
+            undo_stack,
+            redo_stack,
+            ..snapshot
+        };
+    }
+
+    /// Expire regulation time or the current overtime segment if `now` is past it.
+    pub fn tick(&mut self, now: Instant) -> MatchState {
+        if Some(true) == self.expired(now) {
+            self.expire(now);
+        }
+        self.match_state()
+    }
+
+    /// Whether the running regulation or overtime segment clock has run out, or `None`
+    /// if neither is running.
+    fn expired(&self, now: Instant) -> Option<bool> {
+        match (self.state, self.overtime_state) {
+            (RegulationState::NotEngaged | RegulationState::Engaged, _) => {
+                Some(self.regulation_time(now) >= self.rules.regulation)
+            }
+            (RegulationState::Overtime, OvertimeState::Engaged) => {
+                Some(self.overtime_time(now) >= self.segment_duration())
+            }
+            _ => None,
+        }
+    }
+
+    fn change(&mut self, event: Transition, now: Instant) -> Result<(), TransitionError> {
+        // Nothing but the end of the timeout, or the referee stopping the match, gets
+        // the match going again while an injury clock runs.
+        match (self.injury_timeout, event) {
+            (None, Transition::InjuryTimeout(fighter)) => {
+                return self.start_injury_timeout(fighter, now);
+            }
+            (Some(_), Transition::EndInjuryTimeout) => {
+                self.end_injury_timeout(now);
+                return Ok(());
+            }
+            (Some(_), Transition::Stop { .. }) => self.end_injury_timeout(now),
+            (Some(_), _) | (None, Transition::EndInjuryTimeout) => {
+                return Err(self.rejection(event));
+            }
+            (None, _) => {}
+        }
+        // Only the clock running out ends regulation or a segment on time.
+        if Transition::TimeExpire == event && Some(false) == self.expired(now) {
+            return Err(self.rejection(event));
+        }
+        match self.state {
+            RegulationState::Overtime => self.change_overtime(event, now),
+            _ => self.change_regulation(event, now),
+        }
+    }
+
+    /// Pause the match if it is running, and start `fighter`'s injury clock.
+    fn start_injury_timeout(
+        &mut self,
+        fighter: Fighter,
+        now: Instant,
+    ) -> Result<(), TransitionError> {
+        if !self.can_call_injury_timeout() {
+            return Err(self.rejection(Transition::InjuryTimeout(fighter)));
+        }
+        if let (RegulationState::NotEngaged | RegulationState::Engaged, _)
+        | (RegulationState::Overtime, OvertimeState::Engaged) = (self.state, self.overtime_state)
+        {
+            self.change(Transition::Pause, now)?;
+        }
+        self.injury_timeout = Some(fighter);
+        self.start_injury_instant = now;
+        Ok(())
+    }
+
+    fn end_injury_timeout(&mut self, now: Instant) {
+        if let Some(fighter) = self.injury_timeout.take() {
+            self.injury
+                .add(Passivity::Fighter(fighter), now - self.start_injury_instant);
+        }
+    }
+
+    /// The error for `event` in the state the match is in.
+    fn rejection(&self, event: Transition) -> TransitionError {
+        match self.state {
+            RegulationState::Overtime => TransitionError::Overtime {
+                state: self.overtime_state,
+                event,
+            },
+            state => TransitionError::Regulation { state, event },
+        }
+    }
+
+    /// Run the clock out as if [`Transition::TimeExpire`] had been applied, so that
+    /// it can be undone and replays the same way.
+    fn expire(&mut self, now: Instant) {
+        let snapshot = self.snapshot();
+        self.change(Transition::TimeExpire, now)
+            .expect("time can always expire while the clock is running");
+        self.undo_stack.push(snapshot);
+        self.redo_stack.clear();
+        self.record(MatchEvent::Transition(Transition::TimeExpire), now);
+    }
+
+    fn record(&mut self, event: MatchEvent, now: Instant) {
+        let entry = LogEntry::new(
+            self.epoch_offset + (now - self.epoch),
+            self.clock.system_time(),
+            event,
+            self.match_state(),
+        );
+        self.log.push(entry);
+    }
+
+    /// Every transition applied to this match so far.
+    pub fn log(&self) -> &EventLog {
+        &self.log
+    }
+
+    pub fn match_state(&self) -> MatchState {
+        MatchState {
+            regulation: self.state,
+            overtime: self.overtime_state,
+        }
+    }
+
+    pub fn state(&self) -> RegulationState {
+        self.state
+    }
+
+    pub fn overtime_state(&self) -> OvertimeState {
+        self.overtime_state
+    }
+
+    pub fn overtime_segments(&self) -> &[Segment] {
+        &self.overtime_segments
+    }
+
+    pub fn winner(&self) -> Option<Fighter> {
+        self.winner
+    }
+
+    /// How the match ended, if the scores or a regulation submission decided it, or the
+    /// referee stopped it.
+    pub fn result(&self) -> Option<MatchResult> {
+        self.result
+    }
+
+    pub fn win_method(&self) -> Option<WinMethod> {
+        self.result.map(|result| result.method)
+    }
+
+    /// Whether the match has started and not yet ended, so that it is worth resuming.
+    pub fn in_progress(&self) -> bool {
+        match self.state {
+            RegulationState::None | RegulationState::Decided | RegulationState::Stopped => false,
+            RegulationState::Submission => self.result.is_none(),
+            RegulationState::Overtime => !matches!(
+                self.overtime_state,
+                OvertimeState::Win | OvertimeState::Draw
+            ),
+            _ => true,
+        }
+    }
+
+    /// Whether the match has not ended yet, so that the referee can still stop it. Before
+    /// the match, this records a walkover.
+    pub fn can_stop(&self) -> bool {
+        match self.state {
+            RegulationState::None
+            | RegulationState::NotEngaged
+            | RegulationState::Engaged
+            | RegulationState::Paused
+            | RegulationState::Restarted => true,
+            RegulationState::Overtime => !matches!(
+                self.overtime_state,
+                OvertimeState::Win | OvertimeState::Draw
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the match is at a point where a fighter can be given an injury timeout.
+    pub fn can_call_injury_timeout(&self) -> bool {
+        self.injury_timeout.is_none()
+            && matches!(
+                (self.state, self.overtime_state),
+                (
+                    RegulationState::NotEngaged
+                        | RegulationState::Engaged
+                        | RegulationState::Paused,
+                    _
+                ) | (
+                    RegulationState::Overtime,
+                    OvertimeState::CoinToss
+                        | OvertimeState::Paused
+                        | OvertimeState::Engaged
+                        | OvertimeState::AdvanceOvertime
+                )
+            )
+    }
+
+    /// The fighter whose injury timeout is running.
+    pub fn injury_timeout(&self) -> Option<Fighter> {
+        self.injury_timeout
+    }
+
+    /// Injury time `fighter` has used, including the running timeout.
+    pub fn injury_time(&self, fighter: Fighter, now: Instant) -> Duration {
+        let running = if Some(fighter) == self.injury_timeout {
+            now - self.start_injury_instant
+        } else {
+            Duration::from_secs(0)
+        };
+        self.injury.get(fighter) + running
+    }
+
+    /// What is left of `fighter`'s injury allowance.
+    pub fn injury_time_left(&self, fighter: Fighter, now: Instant) -> Duration {
+        self.rules
+            .injury_allowance
+            .saturating_sub(self.injury_time(fighter, now))
+    }
+
+    /// Whether `fighter` has used up their injury allowance, so that the referee should
+    /// stop the match.
+    pub fn injury_allowance_exhausted(&self, fighter: Fighter, now: Instant) -> bool {
+        self.injury_time(fighter, now) >= self.rules.injury_allowance
+    }
+
+    pub fn scores(&self) -> &Scores {
+        &self.scores
+    }
+
+    /// The result of the coin toss, once overtime has started.
+    pub fn toss(&self) -> Option<CoinToss> {
+        self.toss
+    }
+
+    /// The fighter attacking in the first overtime round.
+    pub fn first_attacker(&self) -> Fighter {
+        self.toss
+            .map(|toss| toss.first_attacker())
+            .unwrap_or(Fighter::A)
+    }
+
+    /// The fighter the overtime segment at `index` counts for, the one attacking in it.
+    pub fn segment_fighter(&self, index: usize) -> Fighter {
+        if index % 2 == 0 {
+            self.first_attacker()
+        } else {
+            self.first_attacker().other()
+        }
+    }
+
+    /// The rules this match is run with.
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    pub fn fighters(&self) -> &Fighters {
+        &self.fighters
+    }
+
+    pub fn regulation_duration(&self) -> Duration {
+        self.rules.regulation
+    }
+
+    pub fn penalty_free_duration(&self) -> Duration {
+        self.rules.penalty_free
+    }
+
+    pub fn half_penalty_free_duration(&self) -> Duration {
+        self.rules.penalty_free_split
+    }
+
+    pub fn standard_overtime_duration(&self) -> Duration {
+        self.rules.standard_overtime
+    }
+
+    pub fn total_regulation_duration(&self) -> Duration {
+        self.total_regulation_duration
+    }
+
+    pub fn total_non_engaged_duration(&self) -> Duration {
+        self.total_non_engaged_duration
+    }
+
+    pub fn total_overtime_duration(&self) -> Duration {
+        self.total_overtime_duration
+    }
+
+    pub fn total_penalty_duration(&self) -> Duration {
+        self.total_penalty_duration
+    }
+
+    pub fn penalty_time_divided(&self) -> Duration {
+        self.penalty_time_divided
+    }
+
+    /// Length of one overtime segment, including its share of the penalty time.
+    ///
+    /// With per-fighter stalling penalties, only the penalty of the fighter the segment
+    /// counts for is added.
+    pub fn segment_duration(&self) -> Duration {
+        let penalty = if self.rules.stalling_penalty {
+            let fighter = self.segment_fighter(self.overtime_segments.len());
+            self.fighter_penalty_divided.get(fighter)
+        } else {
+            self.penalty_time_divided
+        };
+        penalty + self.rules.standard_overtime
+    }
+
+    /// Who is held responsible for the fighters not being engaged.
+    pub fn passivity(&self) -> Passivity {
+        self.passivity
+    }
+
+    /// Non engaged time `fighter` was held responsible for, including the running period.
+    pub fn stalling_time(&self, fighter: Fighter, now: Instant) -> Duration {
+        let running = match self.state {
+            RegulationState::NotEngaged if self.passivity.includes(fighter) => {
+                now - self.start_non_engaged_instant
+            }
+            _ => Duration::from_secs(0),
+        };
+        self.stalling.get(fighter) + running
+    }
+
+    /// The share of `fighter`'s stalling penalty added to each segment that counts for them.
+    pub fn fighter_penalty_divided(&self, fighter: Fighter) -> Duration {
+        self.fighter_penalty_divided.get(fighter)
+    }
+
+    /// Regulation time used so far, including the running period.
+    pub fn regulation_time(&self, now: Instant) -> Duration {
+        match self.state {
+            RegulationState::NotEngaged | RegulationState::Engaged => {
+                self.total_regulation_duration + (now - self.start_regulation_instant)
+            }
+            _ => self.total_regulation_duration,
+        }
+    }
+
+    /// Non engaged time used so far, including the running period.
+    pub fn non_engaged_time(&self, now: Instant) -> Duration {
+        match self.state {
+            RegulationState::NotEngaged => {
+                self.total_non_engaged_duration + (now - self.start_non_engaged_instant)
+            }
+            _ => self.total_non_engaged_duration,
+        }
+    }
+
+    /// Time spent in the current overtime segment.
+    pub fn overtime_time(&self, now: Instant) -> Duration {
+        match (self.state, self.overtime_state) {
+            (RegulationState::Overtime, OvertimeState::Engaged) => {
+                self.total_overtime_duration + (now - self.start_overtime_instant)
+            }
+            _ => self.total_overtime_duration,
+        }
+    }
+
+    /// Non engaged time beyond the penalty free allowance.
+    pub fn penalty_time(&self, now: Instant) -> Duration {
+        self.non_engaged_time(now)
+            .saturating_sub(self.rules.penalty_free)
+    }
+
+    pub fn match_stage(&self, now: Instant) -> MatchStage {
+        let current_non_engaged_time = self.non_engaged_time(now);
+        if current_non_engaged_time < self.rules.penalty_free_split {
+            MatchStage::FirstHalfPenaltyFree
+        } else if current_non_engaged_time < self.rules.penalty_free {
+            MatchStage::SecondHalfPenaltyFree
+        } else {
+            MatchStage::Penalty
+        }
+    }
+
+    fn change_regulation_state(
+        &self,
+        event: Transition,
+    ) -> Result<RegulationState, TransitionError> {
+        Ok(match (self.state, event) {
+            (RegulationState::None, Transition::StartRegulation) => RegulationState::Start,
+            (RegulationState::Restarted, Transition::StartRegulation) => RegulationState::Start,
+
+            (RegulationState::Start, Transition::Separate)
+            | (RegulationState::Paused, Transition::Separate)
+            | (RegulationState::Engaged, Transition::Separate) => RegulationState::NotEngaged,
+
+            (RegulationState::NotEngaged, Transition::Engage) => RegulationState::Engaged,
+
+            (RegulationState::Engaged, Transition::Pause)
+            | (RegulationState::NotEngaged, Transition::Pause) => RegulationState::Paused,
+
+            (RegulationState::Engaged, Transition::Submission) => RegulationState::Submission,
+
+            (RegulationState::Engaged, Transition::TimeExpire)
+            | (RegulationState::NotEngaged, Transition::TimeExpire) => {
+                if self.points_leader().is_some() {
+                    RegulationState::Decided
+                } else {
+                    RegulationState::Overtime
+                }
+            }
+
+            (
+                RegulationState::None
+                | RegulationState::NotEngaged
+                | RegulationState::Engaged
+                | RegulationState::Paused
+                | RegulationState::Restarted,
+                Transition::Stop { .. },
+            ) => RegulationState::Stopped,
+
+            (RegulationState::Submission, Transition::Submitted { technique, .. })
+                if technique < self.rules.submissions.len() =>
+            {
+                RegulationState::Submission
+            }
+            (RegulationState::Submission, Transition::Restart) => RegulationState::Restarted,
+            (RegulationState::Submission, Transition::Undo) => RegulationState::NotEngaged,
+
+            (state, event) => return Err(TransitionError::Regulation { state, event }),
+        })
+    }
+
+    /// Who the scores make the winner, when the rules decide regulation on them.
+    fn points_leader(&self) -> Option<(Fighter, WinMethod)> {
+        if self.rules.scoring {
+            self.scores.leader()
+        } else {
+            None
+        }
+    }
+
+    fn regulation_input(&self, event: Transition) -> Transition {
+        if let RegulationState::Start = self.state {
+            return Transition::Separate;
+        }
+        event
+    }
+
+    fn change_regulation(
+        &mut self,
+        event: Transition,
+        now: Instant,
+    ) -> Result<(), TransitionError> {
+        let new_state = self.change_regulation_state(self.regulation_input(event))?;
+        match new_state {
+            RegulationState::Start => {
+                self.start_non_engaged_instant = now;
+                self.total_non_engaged_duration = Duration::from_secs(0);
+                self.stalling = FighterTimes::default();
+                self.passivity = Passivity::Both;
+                self.scores = Scores::default();
+                self.injury = FighterTimes::default();
+                self.injury_timeout = None;
+                self.start_regulation_instant = now;
+                self.total_regulation_duration = Duration::from_secs(0);
+                self.total_penalty_duration = Duration::from_secs(0);
+                self.winner = None;
+                self.result = None;
+            }
+            RegulationState::NotEngaged => {
+                if RegulationState::Submission == self.state {
+                    self.winner = None;
+                    self.result = None;
+                }
+                match self.state {
+                    RegulationState::Paused | RegulationState::Submission => {}
+                    _ => self.total_regulation_duration += now - self.start_regulation_instant,
+                }
+                self.start_regulation_instant = now;
+                self.start_non_engaged_instant = now;
+                self.passivity = Passivity::Both;
+            }
+            RegulationState::Engaged => {
+                self.total_regulation_duration += now - self.start_regulation_instant;
+                self.count_non_engaged(now);
+                self.start_regulation_instant = now;
+                self.start_non_engaged_instant = now;
+            }
+            RegulationState::Paused => {
+                if RegulationState::Engaged == self.state {
+                    self.total_regulation_duration += now - self.start_regulation_instant;
+                    self.start_non_engaged_instant = now;
+                    self.start_regulation_instant = now;
+                }
+                if RegulationState::NotEngaged == self.state {
+                    self.total_regulation_duration += now - self.start_regulation_instant;
+                    self.count_non_engaged(now);
+                    self.start_non_engaged_instant = now;
+                    self.start_regulation_instant = now;
+                }
+            }
+            RegulationState::Overtime => {
+                self.total_regulation_duration += now - self.start_regulation_instant;
+                if RegulationState::NotEngaged == self.state {
+                    self.count_non_engaged(now);
+                }
+                self.calculate_penalty();
+                self.overtime_state = OvertimeState::CoinToss;
+            }
+            RegulationState::Decided => {
+                let time = self.regulation_time(now);
+                self.total_regulation_duration += now - self.start_regulation_instant;
+                if RegulationState::NotEngaged == self.state {
+                    self.count_non_engaged(now);
+                }
+                if let Some((winner, method)) = self.points_leader() {
+                    self.winner = Some(winner);
+                    self.result = Some(MatchResult {
+                        winner,
+                        method,
+                        time,
+                    });
+                }
+            }
+            RegulationState::Stopped => {
+                // Before the match or a restart, the regulation clock hasn't run yet.
+                let time = match self.state {
+                    RegulationState::None | RegulationState::Restarted => Duration::from_secs(0),
+                    _ => self.regulation_time(now),
+                };
+                if let RegulationState::NotEngaged | RegulationState::Engaged = self.state {
+                    self.total_regulation_duration += now - self.start_regulation_instant;
+                }
+                if RegulationState::NotEngaged == self.state {
+                    self.count_non_engaged(now);
+                }
+                if let Transition::Stop { winner, reason } = event {
+                    self.stop(winner, reason, time);
+                }
+            }
+            RegulationState::Restarted => {}
+            RegulationState::Submission => {
+                if RegulationState::Engaged == self.state {
+                    self.total_regulation_duration += now - self.start_regulation_instant;
+                    self.start_non_engaged_instant = now;
+                    self.start_regulation_instant = now;
+                }
+                if let Transition::Submitted { winner, technique } = event {
+                    self.winner = Some(winner);
+                    self.result = Some(MatchResult {
+                        winner,
+                        method: WinMethod::Submission { technique },
+                        time: self.total_regulation_duration,
+                    });
+                }
+            }
+            RegulationState::None => {}
+        }
+        self.state = new_state;
+        // The match clock runs as soon as regulation starts.
+        if RegulationState::Start == self.state {
+            self.change_regulation(Transition::Separate, now)?;
+        }
+        Ok(())
+    }
+
+    fn change_overtime_state(
+        &mut self,
+        event: Transition,
+    ) -> Result<OvertimeState, TransitionError> {
+        Ok(match (self.overtime_state, event) {
+            (OvertimeState::CoinToss, Transition::CoinToss(toss)) => {
+                self.toss = Some(toss);
+                OvertimeState::AdvanceOvertime
+            }
+
+            (OvertimeState::AdvanceOvertime, Transition::Engage) => OvertimeState::Engaged,
+
+            (OvertimeState::Engaged, Transition::Separate) => OvertimeState::Escaped,
+
+            (OvertimeState::Engaged, Transition::Submission) => OvertimeState::Submission,
+
+            (OvertimeState::Engaged, Transition::TimeExpire)
+            | (OvertimeState::Escaped, Transition::TimeExpire)
+            | (OvertimeState::Submission, Transition::TimeExpire) => OvertimeState::AdvanceOvertime,
+
+            (OvertimeState::Engaged, Transition::Win) => OvertimeState::Win,
+
+            (OvertimeState::Engaged, Transition::Draw) => OvertimeState::Draw,
+
+            (OvertimeState::Engaged, Transition::Tie) => OvertimeState::Tied,
+
+            (OvertimeState::Tied, Transition::Decision(fighter)) => {
+                self.winner = Some(fighter);
+                OvertimeState::Win
+            }
+
+            (OvertimeState::Engaged, Transition::Pause) => OvertimeState::Paused,
+
+            (OvertimeState::Paused, Transition::Engage) => OvertimeState::Engaged,
+
+            (OvertimeState::Escaped, Transition::Undo) => {
+                self.overtime_segments.pop();
+                OvertimeState::Engaged
+            }
+            (OvertimeState::Submission, Transition::Undo) => {
+                self.overtime_segments.pop();
+                OvertimeState::Engaged
+            }
+            (OvertimeState::Win, Transition::Undo)
+            | (OvertimeState::Draw, Transition::Undo)
+            | (OvertimeState::Tied, Transition::Undo) => {
+                self.overtime_segments.pop();
+                self.winner = None;
+                OvertimeState::Engaged
+            }
+
+            (state, event) => return Err(TransitionError::Overtime { state, event }),
+        })
+    }
+
+    /// Ending a segment records it, and turns into the outcome of the match if that decides it.
+    fn overtime_input(&mut self, event: Transition, now: Instant) -> Transition {
+        if OvertimeState::Engaged != self.overtime_state {
+            return event;
+        }
+        let segment = match event {
+            Transition::Separate => Segment::Escape(self.overtime_time(now)),
+            Transition::Submission => Segment::Submission(self.overtime_time(now)),
+            Transition::TimeExpire => Segment::Escape(self.segment_duration()),
+            _ => return event,
+        };
+        self.overtime_segments.push(segment);
+        self.calculate_win().unwrap_or(event)
+    }
+
+    fn change_overtime(&mut self, event: Transition, now: Instant) -> Result<(), TransitionError> {
+        if let Transition::Stop { winner, reason } = event {
+            if !self.can_stop() {
+                return Err(TransitionError::Overtime {
+                    state: self.overtime_state,
+                    event,
+                });
+            }
+            // Between segments, the next segment's clock hasn't run yet.
+            let time = match self.overtime_state {
+                OvertimeState::Engaged | OvertimeState::Paused => self.overtime_time(now),
+                _ => Duration::from_secs(0),
+            };
+            if OvertimeState::Engaged == self.overtime_state {
+                self.total_overtime_duration += now - self.start_overtime_instant;
+            }
+            self.state = RegulationState::Stopped;
+            self.stop(winner, reason, time);
+            return Ok(());
+        }
+        let event = self.overtime_input(event, now);
+        let new_state = self.change_overtime_state(event)?;
+        match new_state {
+            OvertimeState::Engaged => {
+                if OvertimeState::AdvanceOvertime == self.overtime_state {
+                    self.total_overtime_duration = Duration::from_secs(0);
+                }
+                self.start_overtime_instant = now;
+            }
+            OvertimeState::Paused => {
+                if OvertimeState::Engaged == self.overtime_state {
+                    self.total_overtime_duration += now - self.start_overtime_instant;
+                    self.start_overtime_instant = now;
+                }
+            }
+            OvertimeState::CoinToss | OvertimeState::AdvanceOvertime => {}
+            OvertimeState::Submission => {
+                if OvertimeState::Engaged == self.overtime_state {
+                    self.total_overtime_duration += now - self.start_overtime_instant;
+                    self.start_overtime_instant = now;
+                }
+            }
+            OvertimeState::Escaped => {
+                if OvertimeState::Engaged == self.overtime_state {
+                    self.total_overtime_duration += now - self.start_overtime_instant;
+                    self.start_overtime_instant = now;
+                }
+            }
+            OvertimeState::Win | OvertimeState::Draw | OvertimeState::Tied => {
+                if OvertimeState::Engaged == self.overtime_state {
+                    self.total_overtime_duration += now - self.start_overtime_instant;
+                    self.start_overtime_instant = now;
+                }
+            }
+        }
+        self.overtime_state = new_state;
+        Ok(())
+    }
+
+    /// Record the referee stopping the match with the clocks already stopped.
+    fn stop(&mut self, winner: Fighter, reason: Stoppage, time: Duration) {
+        self.winner = Some(winner);
+        self.result = Some(MatchResult {
+            winner,
+            method: WinMethod::Stoppage(reason),
+            time,
+        });
+    }
+
+    /// Share the non engaged time beyond the penalty free allowance over the overtime rounds.
+    fn calculate_penalty(&mut self) {
+        self.total_penalty_duration = self
+            .total_non_engaged_duration
+            .saturating_sub(self.rules.penalty_free);
+        self.penalty_time_divided = self.total_penalty_duration / self.rules.overtime_rounds;
+        // Each fighter serves all of their own penalty in the segments that count for them.
+        let segments_each = (self.rules.overtime_rounds / 2).max(1);
+        let share =
+            |stalling: Duration| stalling.saturating_sub(self.rules.penalty_free) / segments_each;
+        self.fighter_penalty_divided = FighterTimes {
+            a: share(self.stalling.a),
+            b: share(self.stalling.b),
+        };
+    }
+
+    /// How the match ends after the last segment, if it does.
+    fn calculate_win(&mut self) -> Option<Transition> {
+        if self.overtime_segments.len() % 2 != 0 {
+            return None;
+        }
+        let first = self.first_attacker();
+        let second = first.other();
+        let a: Segment = self.overtime_segments[self.overtime_segments.len() - 2];
+        let b: Segment = self.overtime_segments[self.overtime_segments.len() - 1];
+        match (a, b) {
+            (Segment::Submission(t1), Segment::Submission(t2)) => {
+                if t1 < t2 {
+                    self.winner = Some(first);
+                } else {
+                    self.winner = Some(second);
+                }
+                Some(Transition::Win)
+            }
+            (Segment::Submission(_), Segment::Escape(_)) => {
+                self.winner = Some(first);
+                Some(Transition::Win)
+            }
+            (Segment::Escape(_), Segment::Submission(_)) => {
+                self.winner = Some(second);
+                Some(Transition::Win)
+            }
+            (Segment::Escape(_), Segment::Escape(_)) => {
+                if self.overtime_segments.len() < self.rules.overtime_rounds as usize {
+                    return None;
+                }
+                let a: Duration = self.escapes(first).sum();
+                let b: Duration = self.escapes(second).sum();
+                if a == b {
+                    return self.tiebreak();
+                }
+                if a > b {
+                    self.winner = Some(second);
+                } else {
+                    self.winner = Some(first);
+                }
+                Some(Transition::Win)
+            }
+        }
+    }
+
+    /// Follow the tiebreak policy when the escapes are level after every round.
+    fn tiebreak(&mut self) -> Option<Transition> {
+        match self.rules.tiebreak {
+            TiebreakPolicy::SuddenDeath => None,
+            TiebreakPolicy::FastestEscape => {
+                let first = self.first_attacker();
+                let a = self.escapes(first).min();
+                let b = self.escapes(first.other()).min();
+                if a == b {
+                    return None;
+                }
+                if a > b {
+                    self.winner = Some(first.other());
+                } else {
+                    self.winner = Some(first);
+                }
+                Some(Transition::Win)
+            }
+            TiebreakPolicy::RefereeDecision => Some(Transition::Tie),
+            TiebreakPolicy::Draw => Some(Transition::Draw),
+        }
+    }
+
+    /// Escape times of the segments that count for `fighter`.
+    fn escapes(&self, fighter: Fighter) -> impl Iterator<Item = Duration> + '_ {
+        let first = if fighter == self.first_attacker() {
+            0
+        } else {
+            1
+        };
+        self.overtime_segments
+            .iter()
+            .skip(first)
+            .step_by(2)
+            .filter_map(|segment| match segment {
+                Segment::Escape(t) => Some(*t),
+                Segment::Submission(_) => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,293 @@
+use web_time::{Duration, UNIX_EPOCH};
+
+use super::{CoinToss, Fighter, MatchEngine, MatchResult, OvertimeState, RegulationState, Segment};
+use crate::clock::{Clock, SystemClock};
+use crate::event_log::EventLog;
+use crate::fighters::Fighters;
+use crate::rules::RuleSet;
+use crate::scoring::Scores;
+use crate::stalling::{FighterTimes, Passivity};
+
+/// A match as it is written to storage.
+///
+/// An `Instant` means nothing after a restart, so the running clocks are saved as the
+/// time they showed, anchored to the wall-clock time of the save. When the match is
+/// resumed, the time the app was gone for keeps counting on every clock that was running.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SavedMatch {
+    /// Wall-clock time of the save, since the Unix epoch.
+    saved_at: Duration,
+    match_time: Duration,
+    regulation_time: Duration,
+    non_engaged_time: Duration,
+    overtime_time: Duration,
+    #[serde(default)]
+    stalling: FighterTimes,
+    #[serde(default)]
+    rules: RuleSet,
+    #[serde(default)]
+    fighters: Fighters,
+    total_penalty_duration: Duration,
+    penalty_time_divided: Duration,
+    #[serde(default)]
+    passivity: Passivity,
+    #[serde(default)]
+    fighter_penalty_divided: FighterTimes,
+    #[serde(default)]
+    injury_timeout: Option<Fighter>,
+    #[serde(default)]
+    injury: FighterTimes,
+    state: RegulationState,
+    overtime_state: OvertimeState,
+    overtime_segments: Vec<Segment>,
+    #[serde(default)]
+    toss: Option<CoinToss>,
+    #[serde(default)]
+    scores: Scores,
+    winner: Option<Fighter>,
+    #[serde(default)]
+    result: Option<MatchResult>,
+    log: EventLog,
+}
+
+impl MatchEngine {
+    /// Freeze the match so it can be written to storage. The undo history is not kept.
+    pub fn save(&self) -> SavedMatch {
+        let now = self.clock.now();
+        let saved_at = self
+            .clock
+            .system_time()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        SavedMatch {
+            saved_at,
+            match_time: self.epoch_offset + (now - self.epoch),
+            regulation_time: self.regulation_time(now),
+            non_engaged_time: self.non_engaged_time(now),
+            overtime_time: self.overtime_time(now),
+            stalling: FighterTimes {
+                a: self.stalling_time(Fighter::A, now),
+                b: self.stalling_time(Fighter::B, now),
+            },
+            rules: self.rules.clone(),
+            fighters: self.fighters.clone(),
+            total_penalty_duration: self.total_penalty_duration,
+            penalty_time_divided: self.penalty_time_divided,
+            passivity: self.passivity,
+            fighter_penalty_divided: self.fighter_penalty_divided,
+            injury_timeout: self.injury_timeout,
+            injury: FighterTimes {
+                a: self.injury_time(Fighter::A, now),
+                b: self.injury_time(Fighter::B, now),
+            },
+            state: self.state,
+            overtime_state: self.overtime_state,
+            overtime_segments: self.overtime_segments.clone(),
+            toss: self.toss,
+            scores: self.scores,
+            winner: self.winner,
+            result: self.result,
+            log: self.log.clone(),
+        }
+    }
+}
+
+impl SavedMatch {
+    /// Carry on with the match on `clock`, counting the time since the save on every
+    /// clock that was running.
+    ///
+    /// A save whose rules don't hold up, e.g. one edited by hand, starts a fresh match
+    /// instead.
+    pub fn resume(self, clock: impl Clock + 'static) -> MatchEngine {
+        if let Err(error) = self.rules.validate() {
+            log::warn!("Discarding a saved match with invalid rules: {}", error);
+            return MatchEngine::default().with_clock(clock);
+        }
+        let gap = clock
+            .system_time()
+            .duration_since(UNIX_EPOCH + self.saved_at)
+            .unwrap_or_default();
+        let running = |is_running: bool| {
+            if is_running {
+                gap
+            } else {
+                Duration::from_secs(0)
+            }
+        };
+        let regulation_running = matches!(
+            self.state,
+            RegulationState::NotEngaged | RegulationState::Engaged
+        );
+        let non_engaged_running = RegulationState::NotEngaged == self.state;
+        let overtime_running = RegulationState::Overtime == self.state
+            && OvertimeState::Engaged == self.overtime_state;
+
+        let mut engine = MatchEngine::new(self.rules)
+            .with_fighters(self.fighters)
+            .with_clock(clock);
+        engine.epoch_offset = self.match_time + gap;
+        engine.total_regulation_duration = self.regulation_time + running(regulation_running);
+        engine.total_non_engaged_duration = self.non_engaged_time + running(non_engaged_running);
+        engine.total_overtime_duration = self.overtime_time + running(overtime_running);
+        engine.stalling = self.stalling;
+        engine
+            .stalling
+            .add(self.passivity, running(non_engaged_running));
+        engine.passivity = self.passivity;
+        engine.fighter_penalty_divided = self.fighter_penalty_divided;
+        engine.injury_timeout = self.injury_timeout;
+        engine.injury = self.injury;
+        if let Some(fighter) = self.injury_timeout {
+            engine.injury.add(Passivity::Fighter(fighter), gap);
+        }
+        engine.total_penalty_duration = self.total_penalty_duration;
+        engine.penalty_time_divided = self.penalty_time_divided;
+        engine.state = self.state;
+        engine.overtime_state = self.overtime_state;
+        engine.overtime_segments = self.overtime_segments;
+        engine.toss = self.toss;
+        engine.scores = self.scores;
+        engine.winner = self.winner;
+        engine.result = self.result;
+        engine.log = self.log;
+        engine
+    }
+}
+
+impl From<MatchEngine> for SavedMatch {
+    fn from(engine: MatchEngine) -> Self {
+        engine.save()
+    }
+}
+
+impl From<SavedMatch> for MatchEngine {
+    fn from(saved: SavedMatch) -> Self {
+        saved.resume(SystemClock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::engine::Transition;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn running_clocks_count_the_time_away() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        clock.advance(secs(30));
+        let saved = engine.save();
+        clock.advance(secs(20));
+
+        let mut engine = saved.resume(clock.clone());
+        assert_eq!(RegulationState::NotEngaged, engine.state());
+        assert_eq!(secs(50), engine.regulation_time(engine.now()));
+        assert_eq!(secs(50), engine.non_engaged_time(engine.now()));
+        clock.advance(secs(5));
+        engine.apply(Transition::Engage).unwrap();
+        assert_eq!(secs(55), engine.total_non_engaged_duration());
+        assert_eq!(2, engine.log().len());
+        assert_eq!(secs(55), engine.log().entries()[1].at);
+    }
+
+    #[test]
+    fn a_save_with_invalid_rules_starts_a_fresh_match() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        let mut saved = engine.save();
+        saved.rules.overtime_rounds = 0;
+
+        let mut engine = saved.resume(clock.clone());
+        assert_eq!(RegulationState::None, engine.state());
+        assert_eq!(&RuleSet::default(), engine.rules());
+        engine.apply(Transition::StartRegulation).unwrap();
+        clock.advance(secs(600));
+        assert_eq!(
+            RegulationState::Overtime,
+            engine.tick(engine.now()).regulation
+        );
+    }
+
+    #[test]
+    fn a_save_from_before_rules_and_fighters_still_resumes() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        clock.advance(secs(30));
+        let mut json = serde_json::to_value(engine.save()).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        for field in [
+            "stalling",
+            "rules",
+            "fighters",
+            "passivity",
+            "fighter_penalty_divided",
+            "injury_timeout",
+            "injury",
+            "toss",
+            "scores",
+            "result",
+        ] {
+            fields.remove(field).unwrap();
+        }
+
+        let saved: SavedMatch = serde_json::from_value(json).unwrap();
+        let engine = saved.resume(clock.clone());
+        assert_eq!(RegulationState::NotEngaged, engine.state());
+        assert_eq!(secs(30), engine.regulation_time(engine.now()));
+    }
+
+    #[test]
+    fn paused_clocks_stay_paused() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        clock.advance(secs(30));
+        engine.apply(Transition::Pause).unwrap();
+        let saved = engine.save();
+        clock.advance(secs(600));
+
+        let engine = saved.resume(clock.clone());
+        assert_eq!(RegulationState::Paused, engine.state());
+        assert_eq!(secs(30), engine.regulation_time(engine.now()));
+        assert_eq!(secs(30), engine.non_engaged_time(engine.now()));
+    }
+
+    #[test]
+    fn a_running_injury_timeout_keeps_counting() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        engine.apply(Transition::InjuryTimeout(Fighter::A)).unwrap();
+        clock.advance(secs(30));
+        let saved = engine.save();
+        clock.advance(secs(20));
+
+        let engine = saved.resume(clock.clone());
+        assert_eq!(Some(Fighter::A), engine.injury_timeout());
+        assert_eq!(secs(50), engine.injury_time(Fighter::A, engine.now()));
+        assert_eq!(secs(0), engine.regulation_time(engine.now()));
+    }
+
+    #[test]
+    fn engine_round_trips_through_json() {
+        let mut fighters = Fighters::default();
+        fighters.a.name = "Chan".to_owned();
+        let mut engine = MatchEngine::default().with_fighters(fighters);
+        engine.apply(Transition::StartRegulation).unwrap();
+        engine.apply(Transition::Engage).unwrap();
+        let json = serde_json::to_string(&engine).unwrap();
+        let restored: MatchEngine = serde_json::from_str(&json).unwrap();
+        assert_eq!(engine.match_state(), restored.match_state());
+        assert_eq!(engine.log(), restored.log());
+        assert_eq!(engine.regulation_duration(), restored.regulation_duration());
+        assert_eq!(engine.fighters(), restored.fighters());
+    }
+}
@@ -0,0 +1,1308 @@
+use proptest::prelude::*;
+use web_time::Duration;
+
+use super::*;
+use crate::clock::ManualClock;
+use crate::event_log::{EventLog, MatchEvent};
+use crate::rules::{MT, PFT, SOT};
+use crate::scoring::{ScoreChange, ScoreKind};
+use crate::stalling::Passivity;
+
+const ALL_REGULATION_STATES: [RegulationState; 10] = [
+    RegulationState::Start,
+    RegulationState::Restarted,
+    RegulationState::NotEngaged,
+    RegulationState::Paused,
+    RegulationState::Engaged,
+    RegulationState::Overtime,
+    RegulationState::Submission,
+    RegulationState::Decided,
+    RegulationState::Stopped,
+    RegulationState::None,
+];
+
+const ALL_OVERTIME_STATES: [OvertimeState; 9] = [
+    OvertimeState::CoinToss,
+    OvertimeState::Paused,
+    OvertimeState::Engaged,
+    OvertimeState::Escaped,
+    OvertimeState::Submission,
+    OvertimeState::AdvanceOvertime,
+    OvertimeState::Win,
+    OvertimeState::Draw,
+    OvertimeState::Tied,
+];
+
+const ALL_TRANSITIONS: [Transition; 19] = [
+    Transition::StartRegulation,
+    Transition::StartOvertime,
+    Transition::Restart,
+    Transition::Pause,
+    Transition::Engage,
+    Transition::Separate,
+    Transition::TimeExpire,
+    Transition::Submission,
+    Transition::Undo,
+    Transition::Win,
+    Transition::Draw,
+    Transition::Tie,
+    Transition::Decision(Fighter::A),
+    Transition::Decision(Fighter::B),
+    Transition::CoinToss(CoinToss {
+        chooser: Fighter::B,
+        position: Position::Defend,
+    }),
+    Transition::Stop {
+        winner: Fighter::B,
+        reason: Stoppage::Disqualification,
+    },
+    Transition::InjuryTimeout(Fighter::A),
+    Transition::EndInjuryTimeout,
+    Transition::Submitted {
+        winner: Fighter::A,
+        technique: 0,
+    },
+];
+
+fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+fn engine(clock: &ManualClock) -> MatchEngine {
+    MatchEngine::default().with_clock(clock.clone())
+}
+
+/// A started engine that has run out of regulation time without any penalty.
+fn overtime_engine(clock: &ManualClock) -> MatchEngine {
+    let mut engine = engine(clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(MT));
+    engine.tick(engine.now());
+    assert_eq!(RegulationState::Overtime, engine.state());
+    toss(&mut engine, Fighter::A);
+    engine
+}
+
+/// Settle the coin toss with `first` choosing to attack first.
+fn toss(engine: &mut MatchEngine, first: Fighter) {
+    let toss = CoinToss {
+        chooser: first,
+        position: Position::Attack,
+    };
+    engine.apply(Transition::CoinToss(toss)).unwrap();
+}
+
+/// Run one overtime segment that ends with `event` after `length`.
+fn segment(engine: &mut MatchEngine, clock: &ManualClock, length: u64, event: Transition) {
+    assert_eq!(OvertimeState::AdvanceOvertime, engine.overtime_state());
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(length));
+    match engine.apply(event).unwrap().overtime {
+        OvertimeState::Escaped | OvertimeState::Submission => {
+            engine.apply(Transition::TimeExpire).unwrap();
+        }
+        _ => {}
+    }
+}
+
+fn expected_regulation(state: RegulationState, event: Transition) -> Option<RegulationState> {
+    Some(match (state, event) {
+        (RegulationState::None | RegulationState::Restarted, Transition::StartRegulation) => {
+            RegulationState::NotEngaged
+        }
+        // No injury clock is running, and Start is over before anyone could get hurt.
+        (_, Transition::EndInjuryTimeout)
+        | (RegulationState::Start, Transition::InjuryTimeout(_)) => return None,
+        // Start is only passed through on the way to NotEngaged.
+        (RegulationState::Start, _) => RegulationState::NotEngaged,
+        (
+            RegulationState::NotEngaged | RegulationState::Engaged | RegulationState::Paused,
+            Transition::InjuryTimeout(_),
+        ) => RegulationState::Paused,
+        (RegulationState::Paused | RegulationState::Engaged, Transition::Separate) => {
+            RegulationState::NotEngaged
+        }
+        (RegulationState::NotEngaged, Transition::Engage) => RegulationState::Engaged,
+        (RegulationState::Engaged | RegulationState::NotEngaged, Transition::Pause) => {
+            RegulationState::Paused
+        }
+        (RegulationState::Engaged, Transition::Submission) => RegulationState::Submission,
+        // Regulation still has all its time left, so it can't expire.
+        (
+            RegulationState::None
+            | RegulationState::NotEngaged
+            | RegulationState::Engaged
+            | RegulationState::Paused
+            | RegulationState::Restarted,
+            Transition::Stop { .. },
+        ) => RegulationState::Stopped,
+        (RegulationState::Submission, Transition::Submitted { .. }) => RegulationState::Submission,
+        (RegulationState::Submission, Transition::Restart) => RegulationState::Restarted,
+        (RegulationState::Submission, Transition::Undo) => RegulationState::NotEngaged,
+        _ => return None,
+    })
+}
+
+fn expected_overtime(state: OvertimeState, event: Transition) -> Option<OvertimeState> {
+    Some(match (state, event) {
+        (OvertimeState::CoinToss, Transition::CoinToss(_)) => OvertimeState::AdvanceOvertime,
+        (OvertimeState::AdvanceOvertime | OvertimeState::Paused, Transition::Engage) => {
+            OvertimeState::Engaged
+        }
+        (OvertimeState::Engaged, Transition::Separate) => OvertimeState::Escaped,
+        (OvertimeState::Engaged, Transition::Submission) => OvertimeState::Submission,
+        (OvertimeState::Escaped | OvertimeState::Submission, Transition::TimeExpire) => {
+            OvertimeState::AdvanceOvertime
+        }
+        (OvertimeState::Engaged, Transition::Win) => OvertimeState::Win,
+        (OvertimeState::Engaged, Transition::Draw) => OvertimeState::Draw,
+        (OvertimeState::Engaged, Transition::Tie) => OvertimeState::Tied,
+        (OvertimeState::Tied, Transition::Decision(_)) => OvertimeState::Win,
+        (OvertimeState::Engaged, Transition::Pause | Transition::InjuryTimeout(_)) => {
+            OvertimeState::Paused
+        }
+        (
+            OvertimeState::CoinToss | OvertimeState::Paused | OvertimeState::AdvanceOvertime,
+            Transition::InjuryTimeout(_),
+        ) => state,
+        (
+            OvertimeState::Escaped
+            | OvertimeState::Submission
+            | OvertimeState::Win
+            | OvertimeState::Draw
+            | OvertimeState::Tied,
+            Transition::Undo,
+        ) => OvertimeState::Engaged,
+        _ => return None,
+    })
+}
+
+#[test]
+fn every_regulation_transition() {
+    let clock = ManualClock::new();
+    for state in ALL_REGULATION_STATES {
+        if RegulationState::Overtime == state {
+            continue;
+        }
+        for event in ALL_TRANSITIONS {
+            let mut engine = engine(&clock);
+            engine.state = state;
+            let result = engine.apply(event).map(|state| state.regulation);
+            let expected = expected_regulation(state, event)
+                .ok_or(TransitionError::Regulation { state, event });
+            assert_eq!(expected, result, "{:?} + {:?}", state, event);
+            assert_eq!(expected.unwrap_or(state), engine.state());
+        }
+    }
+}
+
+#[test]
+fn every_overtime_transition() {
+    let clock = ManualClock::new();
+    for state in ALL_OVERTIME_STATES {
+        // Stopping leaves overtime, see `the_referee_can_stop_overtime`.
+        for event in ALL_TRANSITIONS
+            .into_iter()
+            .filter(|event| !matches!(event, Transition::Stop { .. }))
+        {
+            let mut engine = engine(&clock);
+            engine.state = RegulationState::Overtime;
+            engine.overtime_state = state;
+            let result = engine.apply(event).map(|state| state.overtime);
+            let expected =
+                expected_overtime(state, event).ok_or(TransitionError::Overtime { state, event });
+            assert_eq!(expected, result, "{:?} + {:?}", state, event);
+            assert_eq!(RegulationState::Overtime, engine.state());
+            assert_eq!(expected.unwrap_or(state), engine.overtime_state());
+        }
+    }
+}
+
+#[test]
+fn rejected_transition_keeps_the_match_running() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(30));
+    assert_eq!(
+        Err(TransitionError::Regulation {
+            state: RegulationState::Engaged,
+            event: Transition::Undo
+        }),
+        engine.apply(Transition::Undo)
+    );
+    assert_eq!(RegulationState::Engaged, engine.state());
+    assert_eq!(secs(30), engine.regulation_time(engine.now()));
+}
+
+#[test]
+fn time_only_expires_once_the_clock_runs_out() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(30));
+    assert_eq!(
+        Err(TransitionError::Regulation {
+            state: RegulationState::NotEngaged,
+            event: Transition::TimeExpire
+        }),
+        engine.apply(Transition::TimeExpire)
+    );
+    clock.advance(secs(MT - 30));
+    assert_eq!(
+        RegulationState::Overtime,
+        engine.apply(Transition::TimeExpire).unwrap().regulation
+    );
+
+    toss(&mut engine, Fighter::A);
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(5));
+    assert_eq!(
+        Err(TransitionError::Overtime {
+            state: OvertimeState::Engaged,
+            event: Transition::TimeExpire
+        }),
+        engine.apply(Transition::TimeExpire)
+    );
+    assert!(engine.overtime_segments().is_empty());
+}
+
+#[test]
+fn regulation_durations_accumulate() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(10));
+    engine.apply(Transition::Engage).unwrap();
+    assert_eq!(secs(10), engine.total_regulation_duration());
+    assert_eq!(secs(10), engine.total_non_engaged_duration());
+
+    clock.advance(secs(20));
+    engine.apply(Transition::Separate).unwrap();
+    assert_eq!(secs(30), engine.total_regulation_duration());
+    assert_eq!(secs(10), engine.total_non_engaged_duration());
+
+    clock.advance(secs(5));
+    engine.apply(Transition::Pause).unwrap();
+    assert_eq!(secs(35), engine.total_regulation_duration());
+    assert_eq!(secs(15), engine.total_non_engaged_duration());
+
+    // Paused time counts for nothing.
+    clock.advance(secs(100));
+    engine.apply(Transition::Separate).unwrap();
+    assert_eq!(secs(35), engine.total_regulation_duration());
+    assert_eq!(secs(15), engine.total_non_engaged_duration());
+
+    clock.advance(secs(3));
+    assert_eq!(secs(38), engine.regulation_time(engine.now()));
+    assert_eq!(secs(18), engine.non_engaged_time(engine.now()));
+    engine.apply(Transition::Engage).unwrap();
+    assert_eq!(secs(38), engine.total_regulation_duration());
+    assert_eq!(secs(18), engine.total_non_engaged_duration());
+}
+
+#[test]
+fn pausing_while_engaged_stops_the_clock() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(7));
+    engine.apply(Transition::Pause).unwrap();
+    clock.advance(secs(60));
+    assert_eq!(secs(7), engine.regulation_time(engine.now()));
+    assert_eq!(secs(0), engine.non_engaged_time(engine.now()));
+}
+
+#[test]
+fn match_stage_follows_non_engaged_time() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    assert_eq!(
+        MatchStage::FirstHalfPenaltyFree,
+        engine.match_stage(engine.now())
+    );
+    clock.advance(secs(PFT / 2));
+    assert_eq!(
+        MatchStage::SecondHalfPenaltyFree,
+        engine.match_stage(engine.now())
+    );
+    clock.advance(secs(PFT / 2));
+    assert_eq!(MatchStage::Penalty, engine.match_stage(engine.now()));
+    clock.advance(secs(12));
+    assert_eq!(secs(12), engine.penalty_time(engine.now()));
+}
+
+#[test]
+fn regulation_expires_into_overtime_with_penalty_split() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(PFT + 60));
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(MT - PFT - 61));
+    assert_eq!(
+        RegulationState::Engaged,
+        engine.tick(engine.now()).regulation
+    );
+    clock.advance(secs(1));
+    let state = engine.tick(engine.now());
+    assert_eq!(RegulationState::Overtime, state.regulation);
+    assert_eq!(OvertimeState::CoinToss, state.overtime);
+    assert_eq!(secs(MT), engine.total_regulation_duration());
+    assert_eq!(secs(PFT + 60), engine.total_non_engaged_duration());
+    assert_eq!(secs(60), engine.total_penalty_duration());
+    assert_eq!(secs(10), engine.penalty_time_divided());
+    assert_eq!(secs(SOT + 10), engine.segment_duration());
+}
+
+#[test]
+fn no_penalty_without_exceeding_penalty_free_time() {
+    let clock = ManualClock::new();
+    let engine = overtime_engine(&clock);
+    assert_eq!(secs(0), engine.total_penalty_duration());
+    assert_eq!(secs(0), engine.penalty_time_divided());
+    assert_eq!(secs(SOT), engine.segment_duration());
+}
+
+#[test]
+fn overtime_segment_expires_as_escape() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(SOT - 1));
+    assert_eq!(OvertimeState::Engaged, engine.tick(engine.now()).overtime);
+    clock.advance(secs(1));
+    assert_eq!(
+        OvertimeState::AdvanceOvertime,
+        engine.tick(engine.now()).overtime
+    );
+    assert_eq!(&[Segment::Escape(secs(SOT))], engine.overtime_segments());
+}
+
+#[test]
+fn overtime_pause_stops_segment_clock() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(20));
+    engine.apply(Transition::Pause).unwrap();
+    clock.advance(secs(500));
+    assert_eq!(secs(20), engine.overtime_time(engine.now()));
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(5));
+    engine.apply(Transition::Separate).unwrap();
+    assert_eq!(&[Segment::Escape(secs(25))], engine.overtime_segments());
+}
+
+#[test]
+fn overtime_undo_drops_the_segment() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(20));
+    engine.apply(Transition::Submission).unwrap();
+    assert_eq!(1, engine.overtime_segments().len());
+    assert_eq!(
+        OvertimeState::Engaged,
+        engine.apply(Transition::Undo).unwrap().overtime
+    );
+    assert!(engine.overtime_segments().is_empty());
+}
+
+#[test]
+fn undoing_the_deciding_segment_clears_the_winner() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    segment(&mut engine, &clock, 30, Transition::Submission);
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(40));
+    engine.apply(Transition::Separate).unwrap();
+    assert_eq!(Some(Fighter::A), engine.winner());
+    assert_eq!(
+        OvertimeState::Engaged,
+        engine.apply(Transition::Undo).unwrap().overtime
+    );
+    assert_eq!(None, engine.winner());
+    assert_eq!(
+        None,
+        crate::live::LiveState::new(&engine, engine.now()).winner
+    );
+}
+
+#[test]
+fn only_an_undecided_match_is_in_progress() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    assert!(!engine.in_progress());
+    engine.apply(Transition::StartRegulation).unwrap();
+    assert!(engine.in_progress());
+
+    let mut engine = overtime_engine(&clock);
+    assert!(engine.in_progress());
+    segment(&mut engine, &clock, 30, Transition::Submission);
+    segment(&mut engine, &clock, 40, Transition::Submission);
+    assert_eq!(OvertimeState::Win, engine.overtime_state());
+    assert!(!engine.in_progress());
+}
+
+#[test]
+fn faster_submission_wins() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    segment(&mut engine, &clock, 30, Transition::Submission);
+    segment(&mut engine, &clock, 40, Transition::Submission);
+    assert_eq!(OvertimeState::Win, engine.overtime_state());
+    assert_eq!(Some(Fighter::A), engine.winner());
+
+    let mut engine = overtime_engine(&clock);
+    segment(&mut engine, &clock, 50, Transition::Submission);
+    segment(&mut engine, &clock, 40, Transition::Submission);
+    assert_eq!(Some(Fighter::B), engine.winner());
+}
+
+#[test]
+fn submission_beats_escape() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    segment(&mut engine, &clock, 30, Transition::Submission);
+    segment(&mut engine, &clock, 10, Transition::Separate);
+    assert_eq!(Some(Fighter::A), engine.winner());
+
+    let mut engine = overtime_engine(&clock);
+    segment(&mut engine, &clock, 10, Transition::Separate);
+    segment(&mut engine, &clock, 30, Transition::Submission);
+    assert_eq!(Some(Fighter::B), engine.winner());
+}
+
+#[test]
+fn escapes_are_compared_after_all_rounds() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    for _ in 0..2 {
+        segment(&mut engine, &clock, 10, Transition::Separate);
+        segment(&mut engine, &clock, 20, Transition::Separate);
+    }
+    assert_eq!(OvertimeState::AdvanceOvertime, engine.overtime_state());
+    assert_eq!(None, engine.winner());
+    segment(&mut engine, &clock, 10, Transition::Separate);
+    segment(&mut engine, &clock, 20, Transition::Separate);
+    assert_eq!(OvertimeState::Win, engine.overtime_state());
+    assert_eq!(Some(Fighter::A), engine.winner());
+
+    let mut engine = overtime_engine(&clock);
+    for _ in 0..3 {
+        segment(&mut engine, &clock, 20, Transition::Separate);
+        segment(&mut engine, &clock, 10, Transition::Separate);
+    }
+    assert_eq!(Some(Fighter::B), engine.winner());
+}
+
+#[test]
+fn overtime_rounds_come_from_the_rules() {
+    for rounds in [2, 4, 6] {
+        let clock = ManualClock::new();
+        let rules = RuleSet {
+            overtime_rounds: rounds,
+            ..RuleSet::default()
+        };
+        let mut engine = MatchEngine::new(rules).with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        clock.advance(secs(PFT + 60));
+        engine.apply(Transition::Engage).unwrap();
+        clock.advance(secs(MT));
+        engine.tick(engine.now());
+        toss(&mut engine, Fighter::A);
+        assert_eq!(secs(60 / u64::from(rounds)), engine.penalty_time_divided());
+
+        for _ in 0..rounds / 2 - 1 {
+            segment(&mut engine, &clock, 20, Transition::Separate);
+            segment(&mut engine, &clock, 10, Transition::Separate);
+        }
+        assert_eq!(None, engine.winner(), "{} rounds", rounds);
+        segment(&mut engine, &clock, 20, Transition::Separate);
+        segment(&mut engine, &clock, 10, Transition::Separate);
+        assert_eq!(Some(Fighter::B), engine.winner(), "{} rounds", rounds);
+    }
+}
+
+#[test]
+fn coin_toss_decides_who_attacks_first() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(MT));
+    engine.tick(engine.now());
+    assert_eq!(
+        Err(TransitionError::Overtime {
+            state: OvertimeState::CoinToss,
+            event: Transition::Engage
+        }),
+        engine.apply(Transition::Engage)
+    );
+    let toss = CoinToss {
+        chooser: Fighter::A,
+        position: Position::Defend,
+    };
+    engine.apply(Transition::CoinToss(toss)).unwrap();
+    assert_eq!(Fighter::B, engine.first_attacker());
+    assert_eq!(Fighter::B, engine.segment_fighter(0));
+    assert_eq!(Fighter::A, engine.segment_fighter(1));
+
+    for _ in 0..3 {
+        segment(&mut engine, &clock, 10, Transition::Separate);
+        segment(&mut engine, &clock, 20, Transition::Separate);
+    }
+    assert_eq!(Some(Fighter::B), engine.winner());
+}
+
+#[test]
+fn equal_escapes_do_not_decide() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    for _ in 0..3 {
+        segment(&mut engine, &clock, 15, Transition::Separate);
+        segment(&mut engine, &clock, 15, Transition::Separate);
+    }
+    assert_eq!(OvertimeState::AdvanceOvertime, engine.overtime_state());
+    assert_eq!(None, engine.winner());
+}
+
+/// An overtime engine whose escapes end level after every round.
+fn level_overtime(clock: &ManualClock, tiebreak: TiebreakPolicy) -> MatchEngine {
+    let rules = RuleSet {
+        tiebreak,
+        ..RuleSet::default()
+    };
+    let mut engine = MatchEngine::new(rules).with_clock(clock.clone());
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(MT));
+    engine.tick(engine.now());
+    toss(&mut engine, Fighter::A);
+    for (a, b) in [(15, 20), (20, 10), (15, 20)] {
+        segment(&mut engine, clock, a, Transition::Separate);
+        segment(&mut engine, clock, b, Transition::Separate);
+    }
+    engine
+}
+
+#[test]
+fn tiebreak_policy_settles_level_escapes() {
+    let clock = ManualClock::new();
+
+    let engine = level_overtime(&clock, TiebreakPolicy::SuddenDeath);
+    assert_eq!(OvertimeState::AdvanceOvertime, engine.overtime_state());
+
+    let engine = level_overtime(&clock, TiebreakPolicy::FastestEscape);
+    assert_eq!(OvertimeState::Win, engine.overtime_state());
+    assert_eq!(Some(Fighter::B), engine.winner());
+
+    let engine = level_overtime(&clock, TiebreakPolicy::Draw);
+    assert_eq!(OvertimeState::Draw, engine.overtime_state());
+    assert_eq!(None, engine.winner());
+
+    let mut engine = level_overtime(&clock, TiebreakPolicy::RefereeDecision);
+    assert_eq!(OvertimeState::Tied, engine.overtime_state());
+    assert_eq!(None, engine.winner());
+    engine.apply(Transition::Decision(Fighter::A)).unwrap();
+    assert_eq!(OvertimeState::Win, engine.overtime_state());
+    assert_eq!(Some(Fighter::A), engine.winner());
+}
+
+#[test]
+fn regulation_submission_can_be_undone() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(40));
+    engine.apply(Transition::Submission).unwrap();
+    clock.advance(secs(30));
+    assert_eq!(
+        RegulationState::NotEngaged,
+        engine.apply(Transition::Undo).unwrap().regulation
+    );
+    assert_eq!(secs(40), engine.total_regulation_duration());
+    engine.apply(Transition::Engage).unwrap();
+    engine.apply(Transition::Submission).unwrap();
+    assert_eq!(
+        RegulationState::Restarted,
+        engine.apply(Transition::Restart).unwrap().regulation
+    );
+    engine.apply(Transition::StartRegulation).unwrap();
+    assert_eq!(secs(0), engine.total_regulation_duration());
+}
+
+#[test]
+fn regulation_submissions_record_the_winner_and_technique() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(95));
+    engine.apply(Transition::Submission).unwrap();
+    clock.advance(secs(30));
+
+    let unknown = Transition::Submitted {
+        winner: Fighter::B,
+        technique: engine.rules().submissions.len(),
+    };
+    assert_eq!(
+        Err(TransitionError::Regulation {
+            state: RegulationState::Submission,
+            event: unknown,
+        }),
+        engine.apply(unknown)
+    );
+    engine
+        .apply(Transition::Submitted {
+            winner: Fighter::B,
+            technique: 2,
+        })
+        .unwrap();
+    assert_eq!(Some(Fighter::B), engine.winner());
+    assert_eq!(
+        Some(MatchResult {
+            winner: Fighter::B,
+            method: WinMethod::Submission { technique: 2 },
+            time: secs(95),
+        }),
+        engine.result()
+    );
+
+    // Taking the submission back takes the result back with it.
+    engine.apply(Transition::Undo).unwrap();
+    assert_eq!(None, engine.result());
+    assert_eq!(None, engine.winner());
+}
+
+fn any_transition() -> impl Strategy<Value = Transition> {
+    proptest::sample::select(ALL_TRANSITIONS.to_vec())
+}
+
+proptest! {
+    #[test]
+    fn clocks_stay_consistent(steps in proptest::collection::vec((any_transition(), 0u64..200), 0..80)) {
+        let clock = ManualClock::new();
+        let mut engine = engine(&clock);
+        engine.apply(Transition::StartRegulation).unwrap();
+        for (event, wait) in steps {
+            clock.advance(secs(wait));
+            let now = engine.now();
+            let state = engine.tick(now);
+            if let RegulationState::NotEngaged | RegulationState::Engaged = state.regulation {
+                prop_assert!(engine.regulation_time(now) < engine.regulation_duration());
+            }
+            if let (RegulationState::Overtime, OvertimeState::Engaged) = (state.regulation, state.overtime) {
+                prop_assert!(engine.overtime_time(now) < engine.segment_duration());
+            }
+            let before = (engine.match_state(), engine.overtime_segments().to_vec(), engine.total_regulation_duration());
+            if engine.apply(event).is_err() {
+                prop_assert_eq!(before, (engine.match_state(), engine.overtime_segments().to_vec(), engine.total_regulation_duration()));
+            }
+            prop_assert!(engine.total_non_engaged_duration() <= engine.total_regulation_duration());
+            prop_assert!(engine.total_regulation_duration() <= clock.elapsed());
+            prop_assert!(engine.penalty_time_divided() * engine.rules().overtime_rounds <= engine.total_penalty_duration());
+        }
+    }
+}
+
+#[test]
+fn log_records_applied_and_expired_transitions() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(5));
+    engine.apply(Transition::Engage).unwrap();
+    engine.apply(Transition::Restart).unwrap_err();
+    clock.advance(secs(MT));
+    engine.tick(engine.now());
+
+    let entries = engine.log().entries();
+    assert_eq!(
+        vec![
+            (
+                secs(0),
+                MatchEvent::Transition(Transition::StartRegulation),
+                RegulationState::NotEngaged
+            ),
+            (
+                secs(5),
+                MatchEvent::Transition(Transition::Engage),
+                RegulationState::Engaged
+            ),
+            (
+                secs(MT + 5),
+                MatchEvent::Transition(Transition::TimeExpire),
+                RegulationState::Overtime
+            ),
+        ],
+        entries
+            .iter()
+            .map(|entry| (entry.at, entry.event, entry.state.regulation))
+            .collect::<Vec<_>>()
+    );
+    assert!(entries[0].wall_clock_ms + 5000 <= entries[1].wall_clock_ms);
+
+    let json = engine.log().to_json().unwrap();
+    assert_eq!(engine.log(), &EventLog::from_json(&json).unwrap());
+}
+
+#[test]
+fn undo_takes_back_a_mistaken_engage() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(10));
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(5));
+    assert_eq!(
+        Some(RegulationState::NotEngaged),
+        engine.undo().map(|state| state.regulation)
+    );
+    // The fighters were never engaged, so all of it was non engaged time.
+    assert_eq!(secs(15), engine.non_engaged_time(engine.now()));
+    assert_eq!(secs(15), engine.regulation_time(engine.now()));
+
+    assert!(engine.can_redo());
+    assert_eq!(
+        Some(RegulationState::Engaged),
+        engine.redo().map(|state| state.regulation)
+    );
+    assert_eq!(secs(10), engine.non_engaged_time(engine.now()));
+}
+
+#[test]
+fn undo_goes_back_several_steps() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    engine.apply(Transition::Pause).unwrap();
+    assert_eq!(RegulationState::Engaged, engine.undo().unwrap().regulation);
+    assert_eq!(
+        RegulationState::NotEngaged,
+        engine.undo().unwrap().regulation
+    );
+    assert_eq!(RegulationState::None, engine.undo().unwrap().regulation);
+    assert_eq!(None, engine.undo());
+
+    engine.redo().unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    assert!(!engine.can_redo());
+    assert_eq!(None, engine.redo());
+}
+
+#[test]
+fn undo_covers_overtime_segments() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    segment(&mut engine, &clock, 30, Transition::Separate);
+    assert_eq!(1, engine.overtime_segments().len());
+    engine.undo().unwrap();
+    assert_eq!(OvertimeState::Escaped, engine.overtime_state());
+    engine.undo().unwrap();
+    assert_eq!(OvertimeState::Engaged, engine.overtime_state());
+    assert!(engine.overtime_segments().is_empty());
+    assert_eq!(secs(30), engine.overtime_time(engine.now()));
+}
+
+#[test]
+fn undo_and_redo_are_logged() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    engine.undo().unwrap();
+    engine.redo().unwrap();
+    engine.undo().unwrap();
+    let events: Vec<_> = engine
+        .log()
+        .entries()
+        .iter()
+        .map(|entry| entry.event)
+        .collect();
+    assert_eq!(
+        vec![
+            MatchEvent::Transition(Transition::StartRegulation),
+            MatchEvent::Transition(Transition::Engage),
+            MatchEvent::Undo,
+            MatchEvent::Redo,
+            MatchEvent::Undo,
+        ],
+        events
+    );
+    assert_eq!(RegulationState::NotEngaged, engine.state());
+}
+
+#[test]
+fn adjusting_non_engaged_time_moves_the_match_stage() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(40));
+    engine.apply(Transition::Engage).unwrap();
+    engine.adjust(Adjustment {
+        clock: MatchClock::NonEngaged,
+        seconds: 60,
+    });
+    assert_eq!(MatchStage::Penalty, engine.match_stage(engine.now()));
+    assert_eq!(secs(10), engine.penalty_time(engine.now()));
+    engine.adjust(Adjustment {
+        clock: MatchClock::NonEngaged,
+        seconds: -20,
+    });
+    assert_eq!(
+        MatchStage::SecondHalfPenaltyFree,
+        engine.match_stage(engine.now())
+    );
+    engine.adjust(Adjustment {
+        clock: MatchClock::Regulation,
+        seconds: -100,
+    });
+    assert_eq!(secs(0), engine.regulation_time(engine.now()));
+}
+
+#[test]
+fn adjustments_take_time_off_running_clocks() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(30));
+    engine.adjust(Adjustment {
+        clock: MatchClock::Regulation,
+        seconds: -5,
+    });
+    assert_eq!(secs(25), engine.regulation_time(engine.now()));
+    engine.adjust(Adjustment {
+        clock: MatchClock::NonEngaged,
+        seconds: -10,
+    });
+    assert_eq!(secs(20), engine.non_engaged_time(engine.now()));
+    assert_eq!(secs(20), engine.stalling_time(Fighter::A, engine.now()));
+    assert_eq!(secs(20), engine.stalling_time(Fighter::B, engine.now()));
+    clock.advance(secs(10));
+    assert_eq!(secs(35), engine.regulation_time(engine.now()));
+    assert_eq!(secs(30), engine.non_engaged_time(engine.now()));
+    assert_eq!(secs(30), engine.stalling_time(Fighter::A, engine.now()));
+
+    let mut engine = overtime_engine(&clock);
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(20));
+    engine.adjust(Adjustment {
+        clock: MatchClock::Overtime,
+        seconds: -5,
+    });
+    assert_eq!(secs(15), engine.overtime_time(engine.now()));
+    clock.advance(secs(5));
+    engine.apply(Transition::Separate).unwrap();
+    assert_eq!(&[Segment::Escape(secs(20))], engine.overtime_segments());
+}
+
+#[test]
+fn adjusting_in_overtime_recalculates_the_penalty() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    assert_eq!(secs(SOT), engine.segment_duration());
+    engine.adjust(Adjustment {
+        clock: MatchClock::NonEngaged,
+        seconds: PFT as i64 + 60,
+    });
+    assert_eq!(secs(60), engine.total_penalty_duration());
+    assert_eq!(secs(10), engine.penalty_time_divided());
+    assert_eq!(secs(SOT + 10), engine.segment_duration());
+
+    engine.undo().unwrap();
+    assert_eq!(secs(SOT), engine.segment_duration());
+    assert_eq!(
+        Some(MatchEvent::Undo),
+        engine.log().entries().last().map(|entry| entry.event)
+    );
+}
+
+/// A started engine in a points division.
+fn scoring_engine(clock: &ManualClock) -> MatchEngine {
+    let rules = RuleSet {
+        scoring: true,
+        ..RuleSet::default()
+    };
+    let mut engine = MatchEngine::new(rules).with_clock(clock.clone());
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine
+}
+
+fn mark(fighter: Fighter, kind: ScoreKind, count: i32) -> ScoreChange {
+    ScoreChange {
+        fighter,
+        kind,
+        count,
+    }
+}
+
+#[test]
+fn scores_decide_regulation() {
+    let clock = ManualClock::new();
+    let mut engine = scoring_engine(&clock);
+    engine.score(mark(Fighter::A, ScoreKind::Point, 2));
+    engine.score(mark(Fighter::B, ScoreKind::Point, 2));
+    engine.score(mark(Fighter::B, ScoreKind::Advantage, 1));
+    engine.score(mark(Fighter::A, ScoreKind::Penalty, -1));
+    assert_eq!(0, engine.scores().a.penalties);
+    clock.advance(secs(MT));
+    assert_eq!(
+        RegulationState::Decided,
+        engine.tick(engine.now()).regulation
+    );
+    assert_eq!(Some(Fighter::B), engine.winner());
+    assert_eq!(Some(WinMethod::Advantages), engine.win_method());
+    assert_eq!(secs(MT), engine.total_regulation_duration());
+}
+
+#[test]
+fn fewer_penalties_decide_level_points() {
+    let clock = ManualClock::new();
+    let mut engine = scoring_engine(&clock);
+    engine.score(mark(Fighter::A, ScoreKind::Penalty, 1));
+    clock.advance(secs(MT));
+    engine.tick(engine.now());
+    assert_eq!(Some(Fighter::B), engine.winner());
+    assert_eq!(Some(WinMethod::Penalties), engine.win_method());
+}
+
+#[test]
+fn level_scores_go_to_overtime() {
+    let clock = ManualClock::new();
+    let mut engine = scoring_engine(&clock);
+    engine.score(mark(Fighter::A, ScoreKind::Point, 3));
+    engine.score(mark(Fighter::B, ScoreKind::Point, 3));
+    clock.advance(secs(MT));
+    assert_eq!(
+        RegulationState::Overtime,
+        engine.tick(engine.now()).regulation
+    );
+    assert_eq!(None, engine.winner());
+}
+
+#[test]
+fn scores_are_ignored_without_scoring() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.score(mark(Fighter::A, ScoreKind::Point, 4));
+    clock.advance(secs(MT));
+    assert_eq!(
+        RegulationState::Overtime,
+        engine.tick(engine.now()).regulation
+    );
+}
+
+#[test]
+fn score_changes_can_be_undone() {
+    let clock = ManualClock::new();
+    let mut engine = scoring_engine(&clock);
+    engine.score(mark(Fighter::A, ScoreKind::Point, 2));
+    engine.score(mark(Fighter::A, ScoreKind::Point, 4));
+    engine.undo().unwrap();
+    assert_eq!(2, engine.scores().a.points);
+    assert_eq!(
+        Some(&MatchEvent::Score(mark(Fighter::A, ScoreKind::Point, 4))),
+        engine.log().entries().get(2).map(|entry| &entry.event)
+    );
+}
+
+#[test]
+fn a_restarted_match_starts_without_scores() {
+    let clock = ManualClock::new();
+    let mut engine = scoring_engine(&clock);
+    engine.score(mark(Fighter::A, ScoreKind::Point, 4));
+    engine.apply(Transition::Engage).unwrap();
+    engine.apply(Transition::Separate).unwrap();
+    engine.attribute(Passivity::Fighter(Fighter::B));
+    engine.apply(Transition::Engage).unwrap();
+    engine.apply(Transition::Submission).unwrap();
+    engine.apply(Transition::Restart).unwrap();
+    engine.apply(Transition::StartRegulation).unwrap();
+    assert_eq!(&Scores::default(), engine.scores());
+    assert_eq!(Passivity::Both, engine.passivity());
+}
+
+#[test]
+fn stalling_is_attributed_per_fighter() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(10));
+    engine.attribute(Passivity::Fighter(Fighter::A));
+    clock.advance(secs(20));
+    assert_eq!(secs(30), engine.stalling_time(Fighter::A, engine.now()));
+    assert_eq!(secs(10), engine.stalling_time(Fighter::B, engine.now()));
+    engine.apply(Transition::Engage).unwrap();
+    assert_eq!(secs(30), engine.total_non_engaged_duration());
+
+    engine.apply(Transition::Separate).unwrap();
+    assert_eq!(Passivity::Both, engine.passivity());
+    clock.advance(secs(5));
+    assert_eq!(secs(35), engine.stalling_time(Fighter::A, engine.now()));
+    assert_eq!(secs(15), engine.stalling_time(Fighter::B, engine.now()));
+    engine.undo().unwrap();
+    engine.undo().unwrap();
+    assert_eq!(Passivity::Fighter(Fighter::A), engine.passivity());
+    assert_eq!(secs(35), engine.stalling_time(Fighter::A, engine.now()));
+    assert_eq!(secs(10), engine.stalling_time(Fighter::B, engine.now()));
+}
+
+#[test]
+fn stalling_penalty_lengthens_only_that_fighters_segments() {
+    let clock = ManualClock::new();
+    let rules = RuleSet {
+        stalling_penalty: true,
+        ..RuleSet::default()
+    };
+    let mut engine = MatchEngine::new(rules).with_clock(clock.clone());
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.attribute(Passivity::Fighter(Fighter::B));
+    clock.advance(secs(PFT + 60));
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(MT));
+    engine.tick(engine.now());
+    toss(&mut engine, Fighter::A);
+    assert_eq!(secs(10), engine.penalty_time_divided());
+    assert_eq!(secs(0), engine.fighter_penalty_divided(Fighter::A));
+    assert_eq!(secs(20), engine.fighter_penalty_divided(Fighter::B));
+    assert_eq!(secs(SOT), engine.segment_duration());
+    segment(&mut engine, &clock, 30, Transition::Separate);
+    assert_eq!(secs(SOT + 20), engine.segment_duration());
+}
+
+#[test]
+fn adjusting_non_engaged_time_counts_for_the_passive_fighter() {
+    let clock = ManualClock::new();
+    let rules = RuleSet {
+        stalling_penalty: true,
+        ..RuleSet::default()
+    };
+    let mut engine = MatchEngine::new(rules).with_clock(clock.clone());
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(MT));
+    engine.tick(engine.now());
+    assert_eq!(RegulationState::Overtime, engine.state());
+    engine.adjust(Adjustment {
+        clock: MatchClock::NonEngaged,
+        seconds: 120,
+    });
+    assert_eq!(
+        secs(MT + 120),
+        engine.stalling_time(Fighter::B, engine.now())
+    );
+    assert_eq!(
+        secs(MT + 120 - PFT) / 3,
+        engine.fighter_penalty_divided(Fighter::A)
+    );
+
+    engine.attribute(Passivity::Fighter(Fighter::A));
+    engine.adjust(Adjustment {
+        clock: MatchClock::NonEngaged,
+        seconds: -(MT as i64),
+    });
+    assert_eq!(secs(120), engine.stalling_time(Fighter::A, engine.now()));
+    assert_eq!(
+        secs(MT + 120),
+        engine.stalling_time(Fighter::B, engine.now())
+    );
+    assert_eq!(secs(30) / 3, engine.fighter_penalty_divided(Fighter::A));
+}
+
+#[test]
+fn the_referee_can_stop_regulation() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    clock.advance(secs(20));
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(25));
+    let stop = Transition::Stop {
+        winner: Fighter::A,
+        reason: Stoppage::Doctor,
+    };
+    assert_eq!(
+        RegulationState::Stopped,
+        engine.apply(stop).unwrap().regulation
+    );
+    assert_eq!(
+        Some(MatchResult {
+            winner: Fighter::A,
+            method: WinMethod::Stoppage(Stoppage::Doctor),
+            time: secs(45),
+        }),
+        engine.result()
+    );
+    assert_eq!(Some(Fighter::A), engine.winner());
+    clock.advance(secs(MT));
+    assert_eq!(
+        RegulationState::Stopped,
+        engine.tick(engine.now()).regulation
+    );
+    assert_eq!(secs(45), engine.regulation_time(engine.now()));
+    assert!(engine.apply(stop).is_err());
+}
+
+#[test]
+fn the_referee_can_stop_overtime() {
+    let clock = ManualClock::new();
+    let stop = Transition::Stop {
+        winner: Fighter::B,
+        reason: Stoppage::Forfeit,
+    };
+    for state in ALL_OVERTIME_STATES {
+        let mut engine = engine(&clock);
+        engine.state = RegulationState::Overtime;
+        engine.overtime_state = state;
+        let result = engine.apply(stop);
+        if let OvertimeState::Win | OvertimeState::Draw = state {
+            assert_eq!(
+                Err(TransitionError::Overtime { state, event: stop }),
+                result
+            );
+        } else {
+            assert_eq!(
+                RegulationState::Stopped,
+                result.unwrap().regulation,
+                "{:?}",
+                state
+            );
+            assert_eq!(Some(Fighter::B), engine.winner());
+        }
+    }
+
+    let mut engine = overtime_engine(&clock);
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(30));
+    engine.apply(stop).unwrap();
+    clock.advance(secs(30));
+    assert_eq!(secs(30), engine.overtime_time(engine.now()));
+    assert_eq!(Some(secs(30)), engine.result().map(|result| result.time));
+}
+
+#[test]
+fn a_stop_before_any_clock_runs_ends_at_zero() {
+    let clock = ManualClock::new();
+    let stop = Transition::Stop {
+        winner: Fighter::A,
+        reason: Stoppage::Forfeit,
+    };
+    let ended_at = |engine: &MatchEngine| engine.result().map(|result| result.time);
+
+    let mut walkover = engine(&clock);
+    assert!(walkover.can_stop());
+    walkover.apply(stop).unwrap();
+    assert_eq!(Some(secs(0)), ended_at(&walkover));
+
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(30));
+    engine.apply(Transition::Submission).unwrap();
+    engine.apply(Transition::Restart).unwrap();
+    engine.apply(stop).unwrap();
+    assert_eq!(Some(secs(0)), ended_at(&engine));
+
+    let mut engine = overtime_engine(&clock);
+    segment(&mut engine, &clock, 30, Transition::Separate);
+    assert_eq!(OvertimeState::AdvanceOvertime, engine.overtime_state());
+    engine.apply(stop).unwrap();
+    assert_eq!(Some(secs(0)), ended_at(&engine));
+}
+
+#[test]
+fn injury_timeouts_pause_the_match_and_add_up_per_fighter() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(60));
+    engine.apply(Transition::InjuryTimeout(Fighter::B)).unwrap();
+    assert_eq!(RegulationState::Paused, engine.state());
+    assert_eq!(Some(Fighter::B), engine.injury_timeout());
+    assert!(!engine.can_call_injury_timeout());
+
+    // The match can't be restarted until the timeout is over.
+    assert_eq!(
+        Err(TransitionError::Regulation {
+            state: RegulationState::Paused,
+            event: Transition::Separate,
+        }),
+        engine.apply(Transition::Separate)
+    );
+    clock.advance(secs(45));
+    assert_eq!(secs(60), engine.regulation_time(engine.now()));
+    assert_eq!(secs(45), engine.injury_time(Fighter::B, engine.now()));
+    assert_eq!(secs(75), engine.injury_time_left(Fighter::B, engine.now()));
+    assert_eq!(secs(0), engine.injury_time(Fighter::A, engine.now()));
+    engine.apply(Transition::EndInjuryTimeout).unwrap();
+    assert_eq!(None, engine.injury_timeout());
+    clock.advance(secs(30));
+    assert_eq!(secs(45), engine.injury_time(Fighter::B, engine.now()));
+
+    engine.apply(Transition::Separate).unwrap();
+    engine.apply(Transition::InjuryTimeout(Fighter::B)).unwrap();
+    clock.advance(secs(75));
+    assert!(engine.injury_allowance_exhausted(Fighter::B, engine.now()));
+    assert!(!engine.injury_allowance_exhausted(Fighter::A, engine.now()));
+}
+
+#[test]
+fn a_restarted_match_gives_back_the_injury_allowance() {
+    let clock = ManualClock::new();
+    let mut engine = engine(&clock);
+    engine.apply(Transition::StartRegulation).unwrap();
+    engine.apply(Transition::InjuryTimeout(Fighter::B)).unwrap();
+    clock.advance(secs(100));
+    engine.apply(Transition::EndInjuryTimeout).unwrap();
+    engine.apply(Transition::Separate).unwrap();
+    engine.apply(Transition::Engage).unwrap();
+    engine.apply(Transition::Submission).unwrap();
+    engine.apply(Transition::Restart).unwrap();
+    engine.apply(Transition::StartRegulation).unwrap();
+    assert_eq!(secs(0), engine.injury_time(Fighter::B, engine.now()));
+    assert_eq!(None, engine.injury_timeout());
+}
+
+#[test]
+fn an_exhausted_injury_allowance_ends_in_an_injury_stoppage() {
+    let clock = ManualClock::new();
+    let mut engine = overtime_engine(&clock);
+    engine.apply(Transition::Engage).unwrap();
+    clock.advance(secs(20));
+    engine.apply(Transition::InjuryTimeout(Fighter::A)).unwrap();
+    assert_eq!(OvertimeState::Paused, engine.overtime_state());
+    clock.advance(engine.rules().injury_allowance);
+    assert!(engine.injury_allowance_exhausted(Fighter::A, engine.now()));
+
+    let stop = Transition::Stop {
+        winner: Fighter::B,
+        reason: Stoppage::Injury,
+    };
+    assert_eq!(
+        RegulationState::Stopped,
+        engine.apply(stop).unwrap().regulation
+    );
+    assert_eq!(None, engine.injury_timeout());
+    assert_eq!(
+        Some(MatchResult {
+            winner: Fighter::B,
+            method: WinMethod::Stoppage(Stoppage::Injury),
+            time: secs(20),
+        }),
+        engine.result()
+    );
+    clock.advance(secs(30));
+    assert_eq!(
+        engine.rules().injury_allowance,
+        engine.injury_time(Fighter::A, engine.now())
+    );
+}
@@ -0,0 +1,82 @@
+use web_time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::engine::{Adjustment, MatchState, Transition};
+use crate::scoring::ScoreChange;
+use crate::stalling::Passivity;
+
+/// Something the officials did to a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum MatchEvent {
+    Transition(Transition),
+    /// Went back to before the previous action.
+    Undo,
+    /// Took back the last undo.
+    Redo,
+    Adjust(Adjustment),
+    Score(ScoreChange),
+    Attribute(Passivity),
+}
+
+/// One event that was applied to a match.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct LogEntry {
+    /// Monotonic time since the engine was created.
+    pub at: Duration,
+    /// Wall-clock time in milliseconds since the Unix epoch.
+    pub wall_clock_ms: u64,
+    pub event: MatchEvent,
+    /// The state the match was in after the event.
+    pub state: MatchState,
+}
+
+impl LogEntry {
+    pub(crate) fn new(
+        at: Duration,
+        wall_clock: SystemTime,
+        event: MatchEvent,
+        state: MatchState,
+    ) -> Self {
+        let wall_clock_ms = wall_clock
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            at,
+            wall_clock_ms,
+            event,
+            state,
+        }
+    }
+}
+
+/// Everything that happened in a match, in order. Entries are only ever appended.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct EventLog {
+    entries: Vec<LogEntry>,
+}
+
+impl EventLog {
+    pub(crate) fn push(&mut self, entry: LogEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[LogEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
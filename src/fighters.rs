@@ -0,0 +1,109 @@
+use crate::engine::Fighter;
+
+/// Colour of the corner a fighter starts from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Corner {
+    #[default]
+    Red,
+    Blue,
+    White,
+    Black,
+    Green,
+    Yellow,
+}
+
+impl Corner {
+    pub const ALL: [Self; 6] = [
+        Self::Red,
+        Self::Blue,
+        Self::White,
+        Self::Black,
+        Self::Green,
+        Self::Yellow,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Red => "Red",
+            Self::Blue => "Blue",
+            Self::White => "White",
+            Self::Black => "Black",
+            Self::Green => "Green",
+            Self::Yellow => "Yellow",
+        }
+    }
+}
+
+/// Who a fighter is, as entered on the setup screen.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FighterDetails {
+    pub name: String,
+    /// Team or academy.
+    pub team: String,
+    pub weight_class: String,
+    pub corner: Corner,
+}
+
+/// Both fighters of a match.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Fighters {
+    pub a: FighterDetails,
+    pub b: FighterDetails,
+}
+
+impl Default for Fighters {
+    fn default() -> Self {
+        Self {
+            a: FighterDetails {
+                corner: Corner::Red,
+                ..FighterDetails::default()
+            },
+            b: FighterDetails {
+                corner: Corner::Blue,
+                ..FighterDetails::default()
+            },
+        }
+    }
+}
+
+impl Fighters {
+    pub fn get(&self, fighter: Fighter) -> &FighterDetails {
+        match fighter {
+            Fighter::A => &self.a,
+            Fighter::B => &self.b,
+        }
+    }
+
+    pub fn get_mut(&mut self, fighter: Fighter) -> &mut FighterDetails {
+        match fighter {
+            Fighter::A => &mut self.a,
+            Fighter::B => &mut self.b,
+        }
+    }
+
+    /// The fighter's name, or `A` or `B` if none was entered.
+    pub fn name(&self, fighter: Fighter) -> String {
+        let name = self.get(fighter).name.trim();
+        if name.is_empty() {
+            format!("{:?}", fighter)
+        } else {
+            name.to_owned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unnamed_fighters_are_called_a_and_b() {
+        let mut fighters = Fighters::default();
+        fighters.b.name = "Lee".to_owned();
+        assert_eq!("A", fighters.name(Fighter::A));
+        assert_eq!("Lee", fighters.name(Fighter::B));
+        assert_eq!(Corner::Blue, fighters.get(Fighter::B).corner);
+    }
+}
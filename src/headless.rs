@@ -0,0 +1,572 @@
+use std::io::{self, Write};
+use std::time::Duration as PollTimeout;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, execute, queue, terminal};
+use web_time::{Duration, Instant};
+
+use crate::clock::ManualClock;
+use crate::engine::{
+    CoinToss, Fighter, MatchEngine, OvertimeState, Position, RegulationState, Transition,
+    TransitionError,
+};
+use crate::event_log::MatchEvent;
+use crate::live::LiveState;
+use crate::overlay::overlay_fields;
+
+/// How often a script ticks the engine while its clock runs, so that time expires
+/// within this much of when it would in the app.
+const SCRIPT_TICK: Duration = Duration::from_millis(10);
+/// How long the console waits for a key before it ticks and redraws.
+const CONSOLE_TICK: PollTimeout = PollTimeout::from_millis(100);
+
+const KEYS: &str = "g start  e engage  n not engaged  x escape  p pause  s submission  \
+                    t advance round  r restart  u undo  y redo  q quit";
+
+/// Timed events to run a match through, for rehearsing a rule set without the app.
+///
+/// Every line is the time since the script started, as `m:ss` or seconds, and an
+/// event. Events are a [`Transition`] by name, or any [`MatchEvent`] as JSON. A line
+/// with only a time lets the clocks run until then, and `#` starts a comment.
+///
+/// ```text
+/// 0:00 StartRegulation
+/// 0:05 Engage
+/// 1:30.5 Separate
+/// 1:40 {"Score": {"fighter": "A", "kind": "Point", "count": 2}}
+/// 10:00
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    steps: Vec<ScriptStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScriptStep {
+    /// Line number in the script, counting from 1.
+    line: usize,
+    at: Duration,
+    event: Option<MatchEvent>,
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut steps: Vec<ScriptStep> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: String| ScriptError::Parse {
+                line: line_number,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (time, event) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let at = parse_time(time).ok_or_else(|| error(format!("{:?} is not a time", time)))?;
+            if let Some(previous) = steps.last() {
+                if at < previous.at {
+                    return Err(error(format!(
+                        "{} is before the line above",
+                        format_time(at)
+                    )));
+                }
+            }
+            let event = match event.trim() {
+                "" => None,
+                event => Some(
+                    parse_event(event).ok_or_else(|| error(format!("unknown event {}", event)))?,
+                ),
+            };
+            steps.push(ScriptStep {
+                line: line_number,
+                at,
+                event,
+            });
+        }
+        Ok(Self { steps })
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self, ScriptError> {
+        Self::parse(&std::fs::read_to_string(path).map_err(ScriptError::Io)?)
+    }
+
+    /// Run `engine` through the script, writing the match after every event to `out`.
+    ///
+    /// Time expiring in between is written out as well. The match is returned as it
+    /// stands after the last line, and the script stops at the first event it rejects.
+    pub fn run(
+        &self,
+        engine: MatchEngine,
+        out: &mut impl Write,
+    ) -> Result<MatchEngine, ScriptError> {
+        let clock = ManualClock::new();
+        let mut engine = engine.with_clock(clock.clone());
+        for step in &self.steps {
+            while clock.elapsed() < step.at {
+                clock.advance(SCRIPT_TICK.min(step.at - clock.elapsed()));
+                let before = engine.match_state();
+                if engine.tick(engine.now()) != before {
+                    report(out, clock.elapsed(), "time expired", &engine)?;
+                }
+            }
+            let Some(event) = step.event else {
+                continue;
+            };
+            engine
+                .apply_event(event)
+                .map_err(|error| ScriptError::Rejected {
+                    line: step.line,
+                    error,
+                })?;
+            report(out, step.at, &event_label(event), &engine)?;
+        }
+        Ok(engine)
+    }
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// The match didn't allow an event at that point.
+    Rejected {
+        line: usize,
+        error: TransitionError,
+    },
+    Io(io::Error),
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            Self::Rejected { line, error } => write!(f, "line {}: {}", line, error),
+            Self::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// `m:ss`, `m:ss.s` or plain seconds.
+fn parse_time(text: &str) -> Option<Duration> {
+    let (minutes, seconds) = match text.split_once(':') {
+        Some((minutes, seconds)) => (minutes.parse::<u64>().ok()?, seconds),
+        None => (0, text),
+    };
+    let seconds = Duration::try_from_secs_f64(seconds.parse().ok()?).ok()?;
+    Some(Duration::from_secs(minutes * 60) + seconds)
+}
+
+fn parse_event(text: &str) -> Option<MatchEvent> {
+    let quoted = format!("\"{}\"", text);
+    [text, quoted.as_str()].iter().find_map(|json| {
+        serde_json::from_str(json)
+            .map(MatchEvent::Transition)
+            .or_else(|_| serde_json::from_str(json))
+            .ok()
+    })
+}
+
+fn event_label(event: MatchEvent) -> String {
+    match event {
+        MatchEvent::Transition(transition) => format!("{:?}", transition),
+        event => format!("{:?}", event),
+    }
+}
+
+fn report(
+    out: &mut impl Write,
+    at: Duration,
+    what: &str,
+    engine: &MatchEngine,
+) -> Result<(), ScriptError> {
+    let live = LiveState::new(engine, engine.now());
+    writeln!(
+        out,
+        "{:>8}  {:<24} {}",
+        format_time(at),
+        what,
+        status_line(&live)
+    )
+    .map_err(ScriptError::Io)
+}
+
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    match time.subsec_millis() {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        millis => format!("{}:{:02}.{:03}", seconds / 60, seconds % 60, millis),
+    }
+}
+
+/// The match on one line, with the clocks that matter in the state it is in.
+fn status_line(live: &LiveState) -> String {
+    let fields = overlay_fields(live);
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    };
+    let mut line = match field("status") {
+        "" => "waiting".to_owned(),
+        status => status.to_owned(),
+    };
+    if live.regulation == RegulationState::Overtime {
+        line += &format!(
+            " | segment {} ({} left)",
+            field("segment_time"),
+            field("segment_time_left")
+        );
+    } else if live.regulation != RegulationState::None {
+        line += &format!(
+            " | match {} ({} left) | non engaged {} {}",
+            field("match_time"),
+            field("match_time_left"),
+            field("non_engaged_time"),
+            field("stage")
+        );
+    }
+    if live.a.score.is_some() {
+        line += &format!(
+            " | {} {} | {} {}",
+            live.a.name,
+            field("a_score"),
+            live.b.name,
+            field("b_score")
+        );
+    }
+    match field("winner") {
+        "" => {}
+        winner => match field("win_method") {
+            "" => line += &format!(" | WINNER {}", winner),
+            method => line += &format!(" | WINNER {} by {}", winner, method),
+        },
+    }
+    line
+}
+
+/// A match run from the keyboard in a terminal.
+///
+/// Keys are turned into transitions for the state the match is in, and anything the
+/// match rejects is shown instead of applied.
+#[derive(Debug)]
+pub struct Console {
+    engine: MatchEngine,
+    /// Who won the submission being recorded.
+    winner: Fighter,
+    message: String,
+}
+
+impl Console {
+    pub fn new(engine: MatchEngine) -> Self {
+        Self {
+            engine,
+            winner: Fighter::A,
+            message: String::new(),
+        }
+    }
+
+    pub fn engine(&self) -> &MatchEngine {
+        &self.engine
+    }
+
+    /// Handle a key press, returning `false` once the console should close.
+    pub fn key(&mut self, key: char) -> bool {
+        self.message.clear();
+        match key {
+            'q' => return false,
+            'u' => {
+                if self.engine.undo().is_none() {
+                    self.message = "Nothing to undo".to_owned();
+                }
+                return true;
+            }
+            'y' => {
+                if self.engine.redo().is_none() {
+                    self.message = "Nothing to redo".to_owned();
+                }
+                return true;
+            }
+            _ => {}
+        }
+        if let Some(event) = self.transition(key) {
+            if let Err(error) = self.engine.apply(event) {
+                self.message = error.to_string();
+            }
+        }
+        true
+    }
+
+    fn transition(&mut self, key: char) -> Option<Transition> {
+        let pick = match key {
+            'a' => Some(Fighter::A),
+            'b' => Some(Fighter::B),
+            _ => None,
+        };
+        let recording_submission =
+            self.engine.state() == RegulationState::Submission && self.engine.result().is_none();
+        // Time running out ends a segment on its own, only a finished one is advanced.
+        let segment_over = self.engine.state() == RegulationState::Overtime
+            && matches!(
+                self.engine.overtime_state(),
+                OvertimeState::Escaped | OvertimeState::Submission
+            );
+        match (key, pick) {
+            ('g', _) => Some(Transition::StartRegulation),
+            ('e', _) => Some(Transition::Engage),
+            ('n' | 'x', _) => Some(Transition::Separate),
+            ('p', _) => Some(Transition::Pause),
+            ('s', _) => Some(Transition::Submission),
+            ('t', _) if segment_over => Some(Transition::TimeExpire),
+            ('r', _) => Some(Transition::Restart),
+            (_, Some(fighter)) if recording_submission => {
+                self.winner = fighter;
+                None
+            }
+            (_, Some(fighter)) if self.engine.state() == RegulationState::Overtime => {
+                match self.engine.overtime_state() {
+                    OvertimeState::CoinToss => Some(Transition::CoinToss(CoinToss {
+                        chooser: fighter,
+                        position: Position::Attack,
+                    })),
+                    OvertimeState::Tied => Some(Transition::Decision(fighter)),
+                    _ => None,
+                }
+            }
+            ('1'..='9', _) if recording_submission => Some(Transition::Submitted {
+                winner: self.winner,
+                technique: key as usize - '1' as usize,
+            }),
+            _ => {
+                self.message = format!("{:?} does nothing here", key);
+                None
+            }
+        }
+    }
+
+    /// Lines to show for the match at `now`.
+    pub fn screen(&self, now: Instant) -> Vec<String> {
+        let engine = &self.engine;
+        let live = LiveState::new(engine, now);
+        let fighters = engine.fighters();
+        let mut lines = vec![
+            format!(
+                "{}: {} ({}) vs {} ({})",
+                engine.rules().name,
+                live.a.name,
+                fighters.a.corner.label(),
+                live.b.name,
+                fighters.b.corner.label()
+            ),
+            String::new(),
+            status_line(&live),
+            String::new(),
+        ];
+        if let Some(fighter) = live.injury_timeout {
+            lines.push(format!(
+                "Injury time left for {}: {}",
+                live.fighter(fighter).name,
+                format_time(Duration::from_secs(live.fighter(fighter).injury_time_left))
+            ));
+        } else if engine.state() == RegulationState::Submission && engine.result().is_none() {
+            lines.push(format!(
+                "Winner: {} (a or b), then the technique:",
+                live.fighter(self.winner).name
+            ));
+            for (index, technique) in engine.rules().submissions.iter().take(9).enumerate() {
+                lines.push(format!("  {} {}", index + 1, technique));
+            }
+        } else if engine.state() == RegulationState::Overtime {
+            match engine.overtime_state() {
+                OvertimeState::CoinToss => {
+                    lines.push("Who attacks first? (a or b)".to_owned());
+                }
+                OvertimeState::Tied => {
+                    lines.push("Who wins by decision? (a or b)".to_owned());
+                }
+                _ => lines.push(format!("{} attacks", live.fighter(live.attacker).name)),
+            }
+        }
+        lines.push(String::new());
+        lines.push(self.message.clone());
+        lines.push(KEYS.to_owned());
+        lines
+    }
+}
+
+/// Run `console` in the terminal until `q` or Escape is pressed.
+pub fn run_console(mut console: Console) -> io::Result<MatchEngine> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = console_loop(&mut console, &mut stdout);
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result.map(|()| console.engine)
+}
+
+fn console_loop(console: &mut Console, stdout: &mut io::Stdout) -> io::Result<()> {
+    let mut shown = Vec::new();
+    loop {
+        let now = console.engine.now();
+        console.engine.tick(now);
+        let lines = console.screen(now);
+        if lines != shown {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, 0),
+                terminal::Clear(terminal::ClearType::All)
+            )?;
+            for line in &lines {
+                write!(stdout, "{}\r\n", line)?;
+            }
+            stdout.flush()?;
+            shown = lines;
+        }
+        if !event::poll(CONSOLE_TICK)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            let key = match (key.kind, key.code) {
+                (KeyEventKind::Release, _) => continue,
+                (_, KeyCode::Esc) => 'q',
+                (_, KeyCode::Char(key)) => key.to_ascii_lowercase(),
+                _ => continue,
+            };
+            if !console.key(key) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::WinMethod;
+    use crate::rules::RuleSet;
+
+    #[test]
+    fn scripts_run_the_match_and_expire_time() {
+        assert_eq!(
+            Some(MatchEvent::Score(crate::scoring::ScoreChange {
+                fighter: Fighter::A,
+                kind: crate::scoring::ScoreKind::Point,
+                count: 2,
+            })),
+            parse_event(r#"{"Score": {"fighter": "A", "kind": "Point", "count": 2}}"#)
+        );
+        let script = Script::parse(
+            "# a quiet match
+             0:00 StartRegulation
+             0:05 Engage
+             90.5 Separate
+             10:30",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        let engine = script
+            .run(MatchEngine::new(RuleSet::default()), &mut out)
+            .unwrap();
+        assert_eq!(RegulationState::Overtime, engine.state());
+        assert_eq!(
+            Duration::from_millis(5_000 + 600_000 - 90_500),
+            engine.total_non_engaged_duration()
+        );
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("0:05  Engage"), "{}", out);
+        assert!(out.contains("1:30.500  Separate"), "{}", out);
+        assert!(out.contains("10:00  time expired"), "{}", out);
+    }
+
+    #[test]
+    fn scripts_stop_at_the_first_rejected_event() {
+        let script = Script::parse("0 StartRegulation\n0:10 Engage\n0:20 Engage").unwrap();
+        let error = script
+            .run(MatchEngine::default(), &mut io::sink())
+            .unwrap_err();
+        assert!(
+            matches!(error, ScriptError::Rejected { line: 3, .. }),
+            "{}",
+            error
+        );
+
+        for (script, line) in [
+            ("0 Engage\nlater Pause", 2),
+            ("1:00 Pause\n0:30", 2),
+            ("0 Dance", 1),
+        ] {
+            match Script::parse(script) {
+                Err(ScriptError::Parse {
+                    line: error_line, ..
+                }) => assert_eq!(line, error_line),
+                other => panic!("{:?} parsed as {:?}", script, other),
+            }
+        }
+    }
+
+    #[test]
+    fn the_console_only_advances_a_finished_overtime_segment() {
+        let clock = ManualClock::new();
+        let mut console = Console::new(MatchEngine::default().with_clock(clock.clone()));
+        console.key('g');
+        console.key('t');
+        assert_eq!(RegulationState::NotEngaged, console.engine().state());
+
+        clock.advance(Duration::from_secs(600));
+        console.engine.tick(console.engine.now());
+        for key in ['a', 'e', 't'] {
+            console.key(key);
+        }
+        assert_eq!(OvertimeState::Engaged, console.engine().overtime_state());
+        clock.advance(Duration::from_secs(20));
+        for key in ['x', 't'] {
+            console.key(key);
+        }
+        assert_eq!(
+            OvertimeState::AdvanceOvertime,
+            console.engine().overtime_state()
+        );
+        assert_eq!(1, console.engine().overtime_segments().len());
+    }
+
+    #[test]
+    fn the_console_undoes_and_redoes_the_last_change() {
+        let mut console = Console::new(MatchEngine::default().with_clock(ManualClock::new()));
+        for key in ['g', 'e', 'p', 'u'] {
+            assert!(console.key(key));
+        }
+        assert_eq!(RegulationState::Engaged, console.engine().state());
+        assert!(console.key('y'));
+        assert_eq!(RegulationState::Paused, console.engine().state());
+        assert!(console.key('y'));
+        assert!(console
+            .screen(console.engine().now())
+            .iter()
+            .any(|line| line.contains("Nothing to redo")));
+    }
+
+    #[test]
+    fn the_console_records_a_submission_from_keys() {
+        let mut console = Console::new(MatchEngine::default().with_clock(ManualClock::new()));
+        for key in ['g', 'e', 's', 'b', '2'] {
+            assert!(console.key(key));
+        }
+        let result = console.engine().result().unwrap();
+        assert_eq!(Some(Fighter::B), console.engine().winner());
+        assert_eq!(WinMethod::Submission { technique: 1 }, result.method);
+
+        assert!(console.key('e'));
+        assert!(console
+            .screen(console.engine().now())
+            .iter()
+            .any(|line| line.contains("not allowed")));
+        assert!(!console.key('q'));
+    }
+}
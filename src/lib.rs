@@ -1,4 +1,38 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+mod clock;
+mod engine;
+mod event_log;
+mod fighters;
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
+mod live;
+mod overlay;
+mod replay;
+mod rules;
+mod scoring;
+#[cfg(not(target_arch = "wasm32"))]
+mod server;
+mod stalling;
 pub use app::CjjTimer;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use engine::{
+    Adjustment, CoinToss, Fighter, MatchClock, MatchEngine, MatchResult, MatchStage, MatchState,
+    OvertimeState, Position, RegulationState, SavedMatch, Segment, Stoppage, Transition,
+    TransitionError, WinMethod,
+};
+pub use event_log::{EventLog, LogEntry, MatchEvent};
+pub use fighters::{Corner, FighterDetails, Fighters};
+#[cfg(not(target_arch = "wasm32"))]
+pub use headless::{run_console, Console, Script, ScriptError};
+pub use live::{LiveFighter, LiveSegment, LiveState};
+#[cfg(not(target_arch = "wasm32"))]
+pub use overlay::OverlayFiles;
+pub use overlay::{overlay_fields, OverlayTemplate};
+pub use replay::Replay;
+pub use rules::{RuleSet, RuleSetError, TiebreakPolicy};
+pub use scoring::{Score, ScoreChange, ScoreKind, Scores};
+#[cfg(not(target_arch = "wasm32"))]
+pub use server::{RemoteCommand, ScoreboardServer};
+pub use stalling::{FighterTimes, Passivity};
@@ -0,0 +1,171 @@
+use web_time::{Duration, Instant};
+
+use crate::engine::{Fighter, MatchEngine, MatchStage, OvertimeState, RegulationState, Segment};
+use crate::fighters::Corner;
+use crate::scoring::Score;
+
+/// What a display needs to show a match, read from the engine at one instant.
+///
+/// Clocks are whole seconds, so that two states only differ once a display would.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct LiveState {
+    pub regulation: RegulationState,
+    pub overtime: OvertimeState,
+    pub a: LiveFighter,
+    pub b: LiveFighter,
+    pub regulation_time: u64,
+    pub regulation_left: u64,
+    pub non_engaged_time: u64,
+    pub penalty_time: u64,
+    pub stage: MatchStage,
+    /// Time in the current overtime segment.
+    pub segment_time: u64,
+    pub segment_left: u64,
+    pub overtime_rounds: u32,
+    /// Who attacks in the current or next overtime segment.
+    pub attacker: Fighter,
+    pub segments: Vec<LiveSegment>,
+    /// The fighter whose injury clock is running.
+    pub injury_timeout: Option<Fighter>,
+    pub winner: Option<Fighter>,
+    /// How the winner won, when it wasn't in overtime.
+    pub win_method: Option<String>,
+}
+
+/// One fighter as a display shows them.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct LiveFighter {
+    /// The name, or `A` or `B` if none was entered.
+    pub name: String,
+    pub team: String,
+    pub corner: Corner,
+    /// Only kept in a points division.
+    pub score: Option<Score>,
+    pub injury_time_left: u64,
+}
+
+/// A finished overtime segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct LiveSegment {
+    /// The attacking fighter the segment counts for.
+    pub fighter: Fighter,
+    pub submission: bool,
+    pub time: u64,
+}
+
+impl LiveState {
+    pub fn new(engine: &MatchEngine, now: Instant) -> Self {
+        let fighter = |fighter: Fighter| {
+            let details = engine.fighters().get(fighter);
+            LiveFighter {
+                name: engine.fighters().name(fighter),
+                team: details.team.trim().to_owned(),
+                corner: details.corner,
+                score: engine
+                    .rules()
+                    .scoring
+                    .then(|| *engine.scores().get(fighter)),
+                injury_time_left: seconds_left(engine.injury_time_left(fighter, now)),
+            }
+        };
+        let segments = engine
+            .overtime_segments()
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                let (submission, time) = match *segment {
+                    Segment::Escape(time) => (false, time),
+                    Segment::Submission(time) => (true, time),
+                };
+                LiveSegment {
+                    fighter: engine.segment_fighter(index),
+                    submission,
+                    time: time.as_secs(),
+                }
+            })
+            .collect();
+        let segment_time = engine.overtime_time(now);
+        Self {
+            regulation: engine.state(),
+            overtime: engine.overtime_state(),
+            a: fighter(Fighter::A),
+            b: fighter(Fighter::B),
+            regulation_time: engine.regulation_time(now).as_secs(),
+            regulation_left: left(engine.regulation_duration(), engine.regulation_time(now)),
+            non_engaged_time: engine.non_engaged_time(now).as_secs(),
+            penalty_time: engine.penalty_time(now).as_secs(),
+            stage: engine.match_stage(now),
+            segment_time: segment_time.as_secs(),
+            segment_left: left(engine.segment_duration(), segment_time),
+            overtime_rounds: engine.rules().overtime_rounds,
+            attacker: engine.segment_fighter(engine.overtime_segments().len()),
+            segments,
+            injury_timeout: engine.injury_timeout(),
+            winner: engine.winner(),
+            win_method: engine
+                .win_method()
+                .map(|method| method.label(engine.rules()).to_owned()),
+        }
+    }
+
+    pub fn fighter(&self, fighter: Fighter) -> &LiveFighter {
+        match fighter {
+            Fighter::A => &self.a,
+            Fighter::B => &self.b,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a live state only holds JSON friendly values")
+    }
+}
+
+/// Whole seconds left of `total` after `used`.
+fn left(total: Duration, used: Duration) -> u64 {
+    seconds_left(total.saturating_sub(used))
+}
+
+/// Whole seconds of a clock counting down, counting a started second as left so that
+/// it only shows `00:00` once time is up.
+pub(crate) fn seconds_left(left: Duration) -> u64 {
+    left.as_secs() + u64::from(left.subsec_nanos() > 0)
+}
+
+/// A clock as `mm:ss`, the way every display shows it.
+pub(crate) fn clock_text(seconds: u64) -> String {
+    format!("{:02}:{:02}", (seconds / 60) % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::engine::Transition;
+
+    #[test]
+    fn live_state_follows_the_clocks() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        clock.advance(Duration::from_millis(30_500));
+        let live = LiveState::new(&engine, engine.now());
+        assert_eq!(RegulationState::NotEngaged, live.regulation);
+        assert_eq!(30, live.regulation_time);
+        assert_eq!(570, live.regulation_left);
+        assert_eq!(30, live.non_engaged_time);
+        assert_eq!("A", live.a.name);
+        assert_eq!(None, live.a.score);
+        assert!(live.to_json().contains(r#""regulation":"NotEngaged""#));
+    }
+
+    #[test]
+    fn countdowns_show_a_started_second() {
+        assert_eq!(
+            "09:30",
+            clock_text(seconds_left(Duration::from_millis(569_500)))
+        );
+        assert_eq!("00:01", clock_text(seconds_left(Duration::from_millis(1))));
+        assert_eq!("00:00", clock_text(seconds_left(Duration::ZERO)));
+        assert_eq!("01:05", clock_text(65));
+    }
+}
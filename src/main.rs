@@ -6,6 +6,15 @@
 fn main() -> eframe::Result<()> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--headless") {
+        if let Err(error) = headless(&args[1..]) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([400.0, 300.0])
@@ -24,6 +33,37 @@ fn main() -> eframe::Result<()> {
     )
 }
 
+/// `--headless [--rules <file>] [--script <file>]` runs a match in the terminal, or
+/// rehearses a rule set by running a script of timed events through it.
+#[cfg(not(target_arch = "wasm32"))]
+fn headless(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rules = hkcjjtimer::RuleSet::default();
+    let mut script = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut path = || {
+            args.next()
+                .map(std::path::Path::new)
+                .ok_or_else(|| format!("{} needs a file", arg))
+        };
+        match arg.as_str() {
+            "--rules" => rules = hkcjjtimer::RuleSet::load(path()?)?,
+            "--script" => script = Some(hkcjjtimer::Script::load(path()?)?),
+            _ => return Err(format!("unknown argument {}", arg).into()),
+        }
+    }
+    let engine = hkcjjtimer::MatchEngine::new(rules);
+    match script {
+        Some(script) => {
+            script.run(engine, &mut std::io::stdout().lock())?;
+        }
+        None => {
+            hkcjjtimer::run_console(hkcjjtimer::Console::new(engine))?;
+        }
+    }
+    Ok(())
+}
+
 // When compiling to web using trunk:
 #[cfg(target_arch = "wasm32")]
 fn main() {
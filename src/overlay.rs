@@ -0,0 +1,239 @@
+use crate::engine::{Fighter, MatchStage, OvertimeState, RegulationState};
+use crate::live::{clock_text, LiveState};
+
+const DEFAULT_TEMPLATE: &str = include_str!("../assets/overlay.html");
+
+/// The values an overlay can show, by name.
+///
+/// Clocks are `mm:ss`. `stage` is `first-half`, `second-half` or `penalty`, and the
+/// corners are lower case, so that templates can use them as CSS classes.
+pub fn overlay_fields(state: &LiveState) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("status", status(state)),
+        ("match_time", clock_text(state.regulation_time)),
+        ("match_time_left", clock_text(state.regulation_left)),
+        ("non_engaged_time", clock_text(state.non_engaged_time)),
+        ("penalty_time", clock_text(state.penalty_time)),
+        (
+            "stage",
+            match state.stage {
+                MatchStage::FirstHalfPenaltyFree => "first-half",
+                MatchStage::SecondHalfPenaltyFree => "second-half",
+                MatchStage::Penalty => "penalty",
+            }
+            .to_owned(),
+        ),
+        ("segment_time", clock_text(state.segment_time)),
+        ("segment_time_left", clock_text(state.segment_left)),
+        (
+            "winner",
+            state
+                .winner
+                .map(|winner| state.fighter(winner).name.clone())
+                .unwrap_or_default(),
+        ),
+        ("win_method", state.win_method.clone().unwrap_or_default()),
+    ];
+    for (fighter, [name, team, corner, score]) in [
+        (Fighter::A, ["a_name", "a_team", "a_corner", "a_score"]),
+        (Fighter::B, ["b_name", "b_team", "b_corner", "b_score"]),
+    ] {
+        let live = state.fighter(fighter);
+        fields.push((name, live.name.clone()));
+        fields.push((team, live.team.clone()));
+        fields.push((corner, live.corner.label().to_lowercase()));
+        fields.push((
+            score,
+            live.score
+                .map(|score| {
+                    format!(
+                        "{} pts {} adv {} pen",
+                        score.points, score.advantages, score.penalties
+                    )
+                })
+                .unwrap_or_default(),
+        ));
+    }
+    fields
+}
+
+fn status(state: &LiveState) -> String {
+    if let Some(fighter) = state.injury_timeout {
+        return format!("INJURY TIMEOUT {}", state.fighter(fighter).name);
+    }
+    match (state.regulation, state.overtime) {
+        (RegulationState::None, _) => String::new(),
+        (RegulationState::NotEngaged, _) => "NOT ENGAGED".to_owned(),
+        (RegulationState::Engaged, _) => "ENGAGED".to_owned(),
+        (RegulationState::Paused, _) => "PAUSED".to_owned(),
+        (RegulationState::Submission, _) => "SUBMISSION".to_owned(),
+        (RegulationState::Decided | RegulationState::Stopped, _) => "FINAL".to_owned(),
+        (RegulationState::Overtime, OvertimeState::Win | OvertimeState::Draw) => "FINAL".to_owned(),
+        (RegulationState::Overtime, _) => format!(
+            "OVERTIME {} of {}",
+            state.segments.len() + 1,
+            state.overtime_rounds
+        ),
+        (RegulationState::Start | RegulationState::Restarted, _) => String::new(),
+    }
+}
+
+/// An HTML fragment with `{{field}}` placeholders for [`overlay_fields`], e.g.
+///
+/// ```html
+/// <style>.penalty { color: red; }</style>
+/// <div>{{a_name}} vs {{b_name}}</div>
+/// <div class="{{stage}}">{{match_time_left}}</div>
+/// ```
+///
+/// Scripts in a template don't run.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(transparent)]
+pub struct OverlayTemplate {
+    text: String,
+}
+
+impl Default for OverlayTemplate {
+    fn default() -> Self {
+        Self::new(DEFAULT_TEMPLATE)
+    }
+}
+
+impl OverlayTemplate {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into() }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        std::fs::read_to_string(path).map(Self::new)
+    }
+
+    /// The template with every placeholder filled in from `state`.
+    ///
+    /// Unknown placeholders are left as they are.
+    pub fn render(&self, state: &LiveState) -> String {
+        let fields = overlay_fields(state);
+        let mut html = String::with_capacity(self.text.len());
+        let mut rest = self.text.as_str();
+        while let Some(start) = rest.find("{{") {
+            html.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let field = after.find("}}").and_then(|end| {
+                let name = after[..end].trim();
+                fields
+                    .iter()
+                    .find(|(field, _)| *field == name)
+                    .map(|(_, value)| (end, value))
+            });
+            match field {
+                Some((end, value)) => {
+                    html.push_str(&escape(value));
+                    rest = &after[end + 2..];
+                }
+                None => {
+                    html.push_str("{{");
+                    rest = after;
+                }
+            }
+        }
+        html.push_str(rest);
+        html
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Keeps a text file per overlay field up to date, for OBS text sources.
+///
+/// Files are only written when their value changes, and are replaced in one go so
+/// that OBS never reads half a file.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct OverlayFiles {
+    dir: std::path::PathBuf,
+    written: std::collections::HashMap<&'static str, String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl OverlayFiles {
+    /// Write into `dir`, creating it if needed.
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            written: Default::default(),
+        })
+    }
+
+    /// Write `<field>.txt` for every field that changed since the last call.
+    pub fn write(&mut self, state: &LiveState) -> std::io::Result<()> {
+        for (name, value) in overlay_fields(state) {
+            if self.written.get(name) == Some(&value) {
+                continue;
+            }
+            let path = self.dir.join(format!("{}.txt", name));
+            let partial = self.dir.join(format!("{}.txt.partial", name));
+            std::fs::write(&partial, &value)?;
+            std::fs::rename(&partial, &path)?;
+            self.written.insert(name, value);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::engine::{MatchEngine, Transition};
+    use crate::fighters::Fighters;
+    use web_time::Duration;
+
+    fn live_state() -> LiveState {
+        let clock = ManualClock::new();
+        let mut fighters = Fighters::default();
+        fighters.a.name = "Chan <Tiger>".to_owned();
+        let mut engine = MatchEngine::default()
+            .with_fighters(fighters)
+            .with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        clock.advance(Duration::from_secs(60));
+        LiveState::new(&engine, engine.now())
+    }
+
+    #[test]
+    fn templates_are_filled_in_and_escaped() {
+        let template = OverlayTemplate::new(
+            r#"<b class="{{stage}} {{a_corner}}">{{a_name}}</b> {{match_time_left}} {{unknown}}"#,
+        );
+        assert_eq!(
+            r#"<b class="second-half red">Chan &lt;Tiger&gt;</b> 09:00 {{unknown}}"#,
+            template.render(&live_state())
+        );
+        assert!(OverlayTemplate::default()
+            .render(&live_state())
+            .contains("Chan &lt;Tiger&gt;"));
+    }
+
+    #[test]
+    fn text_files_hold_one_field_each() {
+        let dir = std::env::temp_dir().join(format!("hkcjjtimer-overlay-{}", std::process::id()));
+        let mut files = OverlayFiles::new(&dir).unwrap();
+        files.write(&live_state()).unwrap();
+        assert_eq!(
+            "Chan <Tiger>",
+            std::fs::read_to_string(dir.join("a_name.txt")).unwrap()
+        );
+        assert_eq!(
+            "01:00",
+            std::fs::read_to_string(dir.join("match_time.txt")).unwrap()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+use web_time::Duration;
+
+use crate::clock::ManualClock;
+use crate::engine::{MatchEngine, TransitionError};
+use crate::event_log::EventLog;
+
+/// A match rebuilt purely from its log, which can be moved to any point in time.
+///
+/// Every duration, the penalty split, the overtime segments and the winner are
+/// derived again by feeding the logged transitions into a fresh engine.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    rules: MatchEngine,
+    log: EventLog,
+    position: Duration,
+    engine: MatchEngine,
+}
+
+impl Replay {
+    /// Replay `log` with the rules of `rules`, checking that every entry still applies.
+    pub fn new(rules: &MatchEngine, log: EventLog) -> Result<Self, TransitionError> {
+        let rules = MatchEngine::new(rules.rules().clone()).with_fighters(rules.fighters().clone());
+        let length = last_entry_at(&log);
+        let engine = replay(&rules, &log, length)?;
+        Ok(Self {
+            rules,
+            log,
+            position: length,
+            engine,
+        })
+    }
+
+    /// Time of the last logged transition.
+    pub fn length(&self) -> Duration {
+        last_entry_at(&self.log)
+    }
+
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    /// Move the replay to `position` after the match engine was created.
+    pub fn seek(&mut self, position: Duration) {
+        self.position = position;
+        self.engine = replay(&self.rules, &self.log, position)
+            .expect("the log was checked when the replay was created");
+    }
+
+    /// The match as it was at the current position.
+    ///
+    /// Its clock stands still at that position, so `engine.now()` gives the time to read it at.
+    pub fn engine(&self) -> &MatchEngine {
+        &self.engine
+    }
+
+    pub fn log(&self) -> &EventLog {
+        &self.log
+    }
+}
+
+fn last_entry_at(log: &EventLog) -> Duration {
+    log.entries()
+        .last()
+        .map(|entry| entry.at)
+        .unwrap_or_default()
+}
+
+fn replay(
+    rules: &MatchEngine,
+    log: &EventLog,
+    until: Duration,
+) -> Result<MatchEngine, TransitionError> {
+    let clock = ManualClock::new();
+    let mut engine = rules.clone().with_clock(clock.clone());
+    for entry in log.entries().iter().take_while(|entry| entry.at <= until) {
+        clock.set(entry.at);
+        engine.apply_event(entry.event)?;
+    }
+    clock.set(until);
+    Ok(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{CoinToss, Fighter, OvertimeState, Position, RegulationState, Transition};
+    use crate::rules::MT;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn replay_follows_an_undone_expiry() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        clock.advance(secs(5));
+        engine.apply(Transition::Engage).unwrap();
+        clock.advance(secs(MT));
+        engine.tick(engine.now());
+        assert_eq!(RegulationState::Overtime, engine.state());
+        assert_eq!(
+            Some(RegulationState::Engaged),
+            engine.undo().map(|state| state.regulation)
+        );
+        engine.apply(Transition::Separate).unwrap();
+
+        let replay = Replay::new(&engine, engine.log().clone()).unwrap();
+        let replayed = replay.engine();
+        assert_eq!(engine.match_state(), replayed.match_state());
+        assert_eq!(
+            engine.total_regulation_duration(),
+            replayed.total_regulation_duration()
+        );
+        assert_eq!(engine.can_undo(), replayed.can_undo());
+    }
+
+    #[test]
+    fn replay_reproduces_the_match() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        engine.apply(Transition::StartRegulation).unwrap();
+        clock.advance(secs(150));
+        engine.apply(Transition::Engage).unwrap();
+        clock.advance(secs(500));
+        engine.tick(engine.now());
+        let toss = CoinToss {
+            chooser: Fighter::B,
+            position: Position::Attack,
+        };
+        engine.apply(Transition::CoinToss(toss)).unwrap();
+        for length in [30, 20, 30, 20, 30, 20] {
+            engine.apply(Transition::Engage).unwrap();
+            clock.advance(secs(length));
+            if let Ok(state) = engine.apply(Transition::Separate) {
+                if OvertimeState::Escaped == state.overtime {
+                    engine.apply(Transition::TimeExpire).unwrap();
+                }
+            }
+        }
+        assert_eq!(OvertimeState::Win, engine.overtime_state());
+
+        let mut replay = Replay::new(&engine, engine.log().clone()).unwrap();
+        let replayed = replay.engine();
+        assert_eq!(engine.match_state(), replayed.match_state());
+        assert_eq!(engine.winner(), replayed.winner());
+        assert_eq!(engine.overtime_segments(), replayed.overtime_segments());
+        assert_eq!(
+            engine.total_regulation_duration(),
+            replayed.total_regulation_duration()
+        );
+        assert_eq!(
+            engine.total_non_engaged_duration(),
+            replayed.total_non_engaged_duration()
+        );
+        assert_eq!(
+            engine.penalty_time_divided(),
+            replayed.penalty_time_divided()
+        );
+
+        replay.seek(secs(100));
+        let replayed = replay.engine();
+        assert_eq!(RegulationState::NotEngaged, replayed.state());
+        assert_eq!(secs(100), replayed.non_engaged_time(replayed.now()));
+        assert_eq!(secs(10), replayed.penalty_time(replayed.now()));
+    }
+}
@@ -0,0 +1,365 @@
+use web_time::Duration;
+
+pub(crate) const MT: u64 = 600;
+pub(crate) const PFT: u64 = 90;
+pub(crate) const OT_ROUNDS: u32 = 6;
+pub(crate) const SOT: u64 = 120;
+pub(crate) const INJURY: u64 = 120;
+
+/// Submissions the table picks from when a rule file doesn't list its own.
+const SUBMISSIONS: [&str; 10] = [
+    "Rear naked choke",
+    "Guillotine",
+    "Triangle",
+    "Arm triangle",
+    "Armbar",
+    "Kimura",
+    "Americana",
+    "Straight ankle lock",
+    "Heel hook",
+    "Other",
+];
+
+/// What happens when the escapes are level after every overtime round.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TiebreakPolicy {
+    /// Keep going with more rounds until someone wins one.
+    #[default]
+    SuddenDeath,
+    /// The fighter with the fastest single escape wins, or sudden death if those are level too.
+    FastestEscape,
+    /// The referee picks the winner.
+    RefereeDecision,
+    /// The match is declared a draw.
+    Draw,
+}
+
+impl TiebreakPolicy {
+    pub const ALL: [Self; 4] = [
+        Self::SuddenDeath,
+        Self::FastestEscape,
+        Self::RefereeDecision,
+        Self::Draw,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::SuddenDeath => "Sudden death round",
+            Self::FastestEscape => "Fastest single escape",
+            Self::RefereeDecision => "Referee decision",
+            Self::Draw => "Draw",
+        }
+    }
+}
+
+/// The lengths and counts a match is run with.
+///
+/// Durations are whole seconds in rule files, e.g.
+///
+/// ```toml
+/// name = "HK CJJ adult"
+/// regulation = 600
+/// penalty_free = 90
+/// penalty_free_split = 45
+/// overtime_rounds = 6
+/// standard_overtime = 120
+/// tiebreak = "sudden-death"
+/// injury_allowance = 120
+/// submissions = ["Rear naked choke", "Armbar", "Other"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct RuleSet {
+    pub name: String,
+    /// Length of regulation time.
+    #[serde(with = "seconds")]
+    pub regulation: Duration,
+    /// Non engaged time allowed before it counts as penalty time.
+    #[serde(with = "seconds")]
+    pub penalty_free: Duration,
+    /// Non engaged time at which the first half of the penalty free time ends.
+    #[serde(with = "seconds")]
+    pub penalty_free_split: Duration,
+    pub overtime_rounds: u32,
+    /// Length of an overtime segment before its share of the penalty time is added.
+    #[serde(with = "seconds")]
+    pub standard_overtime: Duration,
+    pub tiebreak: TiebreakPolicy,
+    /// Keep points, advantages and penalties, and let them decide the match when
+    /// regulation runs out. Level scores still go to overtime.
+    #[serde(default)]
+    pub scoring: bool,
+    /// Give each fighter a penalty for the non engaged time they were held responsible
+    /// for, instead of sharing one penalty over every overtime segment.
+    #[serde(default)]
+    pub stalling_penalty: bool,
+    /// Injury timeout each fighter may take over the whole match.
+    #[serde(with = "seconds", default = "default_injury_allowance")]
+    pub injury_allowance: Duration,
+    /// Techniques a regulation submission can be recorded as.
+    #[serde(default = "default_submissions")]
+    pub submissions: Vec<String>,
+}
+
+fn default_injury_allowance() -> Duration {
+    Duration::from_secs(INJURY)
+}
+
+fn default_submissions() -> Vec<String> {
+    SUBMISSIONS
+        .iter()
+        .map(|&technique| technique.to_owned())
+        .collect()
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::hk_cjj_adult()
+    }
+}
+
+impl RuleSet {
+    pub fn hk_cjj_adult() -> Self {
+        Self {
+            name: "HK CJJ adult".to_owned(),
+            regulation: Duration::from_secs(MT),
+            penalty_free: Duration::from_secs(PFT),
+            penalty_free_split: Duration::from_secs(PFT / 2),
+            overtime_rounds: OT_ROUNDS,
+            standard_overtime: Duration::from_secs(SOT),
+            tiebreak: TiebreakPolicy::SuddenDeath,
+            scoring: false,
+            stalling_penalty: false,
+            injury_allowance: Duration::from_secs(INJURY),
+            submissions: default_submissions(),
+        }
+    }
+
+    pub fn kids() -> Self {
+        Self {
+            name: "Kids".to_owned(),
+            regulation: Duration::from_secs(240),
+            penalty_free: Duration::from_secs(60),
+            penalty_free_split: Duration::from_secs(30),
+            overtime_rounds: 4,
+            standard_overtime: Duration::from_secs(60),
+            tiebreak: TiebreakPolicy::SuddenDeath,
+            scoring: false,
+            stalling_penalty: false,
+            injury_allowance: Duration::from_secs(INJURY),
+            submissions: default_submissions(),
+        }
+    }
+
+    pub fn superfight() -> Self {
+        Self {
+            name: "Superfight".to_owned(),
+            regulation: Duration::from_secs(1200),
+            penalty_free: Duration::from_secs(180),
+            penalty_free_split: Duration::from_secs(90),
+            overtime_rounds: OT_ROUNDS,
+            standard_overtime: Duration::from_secs(180),
+            tiebreak: TiebreakPolicy::SuddenDeath,
+            scoring: false,
+            stalling_penalty: false,
+            injury_allowance: Duration::from_secs(INJURY),
+            submissions: default_submissions(),
+        }
+    }
+
+    /// The profiles that ship with the app.
+    pub fn profiles() -> Vec<Self> {
+        vec![Self::hk_cjj_adult(), Self::kids(), Self::superfight()]
+    }
+
+    /// Check that a match can be run with these rules.
+    pub fn validate(&self) -> Result<(), RuleSetError> {
+        if self.penalty_free > self.regulation {
+            Err(RuleSetError::PenaltyFreeLongerThanRegulation)
+        } else if self.penalty_free_split > self.penalty_free {
+            Err(RuleSetError::SplitOutsidePenaltyFree)
+        } else if self.overtime_rounds == 0 || self.overtime_rounds % 2 != 0 {
+            Err(RuleSetError::UnevenOvertimeRounds(self.overtime_rounds))
+        } else if self.submissions.is_empty() {
+            Err(RuleSetError::NoSubmissions)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, RuleSetError> {
+        let rules: Self = toml::from_str(toml).map_err(RuleSetError::Toml)?;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("a rule set only holds TOML friendly values")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, RuleSetError> {
+        let rules: Self = serde_json::from_str(json).map_err(RuleSetError::Json)?;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a rule set only holds JSON friendly values")
+    }
+
+    /// Read a `.toml` or `.json` rule file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &std::path::Path) -> Result<Self, RuleSetError> {
+        let text = std::fs::read_to_string(path).map_err(RuleSetError::Io)?;
+        match Format::of(path)? {
+            Format::Toml => Self::from_toml(&text),
+            Format::Json => Self::from_json(&text),
+        }
+    }
+
+    /// Write a `.toml` or `.json` rule file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &std::path::Path) -> Result<(), RuleSetError> {
+        let text = match Format::of(path)? {
+            Format::Toml => self.to_toml(),
+            Format::Json => self.to_json(),
+        };
+        std::fs::write(path, text).map_err(RuleSetError::Io)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+enum Format {
+    Toml,
+    Json,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Format {
+    fn of(path: &std::path::Path) -> Result<Self, RuleSetError> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Ok(Self::Toml),
+            Some("json") => Ok(Self::Json),
+            _ => Err(RuleSetError::UnknownFormat),
+        }
+    }
+}
+
+/// Rules that can't be read, written or used for a match.
+#[derive(Debug)]
+pub enum RuleSetError {
+    PenaltyFreeLongerThanRegulation,
+    SplitOutsidePenaltyFree,
+    UnevenOvertimeRounds(u32),
+    NoSubmissions,
+    UnknownFormat,
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for RuleSetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PenaltyFreeLongerThanRegulation => {
+                write!(f, "the penalty free time must fit inside regulation time")
+            }
+            Self::SplitOutsidePenaltyFree => {
+                write!(
+                    f,
+                    "the penalty free split must fit inside the penalty free time"
+                )
+            }
+            Self::UnevenOvertimeRounds(rounds) => {
+                write!(
+                    f,
+                    "overtime needs an even number of rounds so both fighters attack, not {}",
+                    rounds
+                )
+            }
+            Self::NoSubmissions => write!(f, "list at least one submission technique"),
+            Self::UnknownFormat => write!(f, "rule files must end in .toml or .json"),
+            Self::Toml(error) => write!(f, "invalid TOML rule file: {}", error),
+            Self::Json(error) => write!(f, "invalid JSON rule file: {}", error),
+            Self::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for RuleSetError {}
+
+/// Whole seconds in rule files, rather than serde's `{ secs, nanos }`.
+mod seconds {
+    use web_time::Duration;
+
+    pub fn serialize<S: serde::Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        let secs: u64 = serde::Deserialize::deserialize(deserializer)?;
+        Ok(Duration::from_secs(secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_are_valid() {
+        for rules in RuleSet::profiles() {
+            assert!(rules.validate().is_ok(), "{}", rules.name);
+        }
+    }
+
+    #[test]
+    fn rules_round_trip_through_toml_and_json() {
+        let rules = RuleSet::kids();
+        assert_eq!(rules, RuleSet::from_toml(&rules.to_toml()).unwrap());
+        assert_eq!(rules, RuleSet::from_json(&rules.to_json()).unwrap());
+    }
+
+    #[test]
+    fn rule_files_use_seconds() {
+        let rules = RuleSet::from_toml(
+            r#"
+            name = "Club night"
+            regulation = 300
+            penalty_free = 60
+            penalty_free_split = 20
+            overtime_rounds = 2
+            standard_overtime = 90
+            tiebreak = "sudden-death"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(Duration::from_secs(300), rules.regulation);
+        assert_eq!(Duration::from_secs(20), rules.penalty_free_split);
+        assert_eq!(2, rules.overtime_rounds);
+        assert_eq!(Duration::from_secs(INJURY), rules.injury_allowance);
+        assert_eq!(SUBMISSIONS.len(), rules.submissions.len());
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let rules = RuleSet {
+            penalty_free: Duration::from_secs(700),
+            ..RuleSet::default()
+        };
+        assert!(matches!(
+            RuleSet::from_json(&rules.to_json()),
+            Err(RuleSetError::PenaltyFreeLongerThanRegulation)
+        ));
+        let rules = RuleSet {
+            submissions: vec![],
+            ..RuleSet::default()
+        };
+        assert!(matches!(rules.validate(), Err(RuleSetError::NoSubmissions)));
+    }
+}
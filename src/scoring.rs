@@ -0,0 +1,94 @@
+use crate::engine::{Fighter, WinMethod};
+
+/// The kinds of marks the officials give out in a points division.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum ScoreKind {
+    Point,
+    Advantage,
+    Penalty,
+}
+
+/// One fighter's tally.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Score {
+    pub points: u32,
+    pub advantages: u32,
+    pub penalties: u32,
+}
+
+impl Score {
+    pub fn get(&self, kind: ScoreKind) -> u32 {
+        match kind {
+            ScoreKind::Point => self.points,
+            ScoreKind::Advantage => self.advantages,
+            ScoreKind::Penalty => self.penalties,
+        }
+    }
+
+    fn get_mut(&mut self, kind: ScoreKind) -> &mut u32 {
+        match kind {
+            ScoreKind::Point => &mut self.points,
+            ScoreKind::Advantage => &mut self.advantages,
+            ScoreKind::Penalty => &mut self.penalties,
+        }
+    }
+}
+
+/// Marks given to (or, when `count` is negative, taken back from) a fighter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ScoreChange {
+    pub fighter: Fighter,
+    pub kind: ScoreKind,
+    pub count: i32,
+}
+
+/// Both fighters' tallies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Scores {
+    pub a: Score,
+    pub b: Score,
+}
+
+impl Scores {
+    pub fn get(&self, fighter: Fighter) -> &Score {
+        match fighter {
+            Fighter::A => &self.a,
+            Fighter::B => &self.b,
+        }
+    }
+
+    fn get_mut(&mut self, fighter: Fighter) -> &mut Score {
+        match fighter {
+            Fighter::A => &mut self.a,
+            Fighter::B => &mut self.b,
+        }
+    }
+
+    /// Apply `change`, never taking a tally below zero.
+    pub fn change(&mut self, change: ScoreChange) {
+        let tally = self.get_mut(change.fighter).get_mut(change.kind);
+        *tally = tally.saturating_add_signed(change.count);
+    }
+
+    /// Who is ahead and why: more points, then more advantages, then fewer penalties.
+    pub fn leader(&self) -> Option<(Fighter, WinMethod)> {
+        let (a, b) = (self.a, self.b);
+        if a.points != b.points {
+            Some((leading(a.points > b.points), WinMethod::Points))
+        } else if a.advantages != b.advantages {
+            Some((leading(a.advantages > b.advantages), WinMethod::Advantages))
+        } else if a.penalties != b.penalties {
+            Some((leading(a.penalties < b.penalties), WinMethod::Penalties))
+        } else {
+            None
+        }
+    }
+}
+
+fn leading(a_leads: bool) -> Fighter {
+    if a_leads {
+        Fighter::A
+    } else {
+        Fighter::B
+    }
+}
@@ -0,0 +1,493 @@
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use tiny_http::{Method, Request, Response};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::engine::{MatchState, Transition, TransitionError};
+use crate::live::LiveState;
+
+const SCOREBOARD_PAGE: &str = include_str!("../assets/scoreboard.html");
+const REMOTE_PAGE: &str = include_str!("../assets/remote.html");
+const OVERLAY_PAGE: &str = include_str!("../assets/overlay_page.html");
+
+/// How long a remote command waits for the match to take it.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+/// Commands are a single transition, so anything bigger is not one.
+const MAX_COMMAND_LENGTH: u64 = 4096;
+
+/// Serves the match to every browser on the network.
+///
+/// `GET /` is a scoreboard page, `GET /state` the latest [`LiveState`] as JSON, and
+/// `/ws` a WebSocket that is sent the state every time it changes. `GET /overlay` is a
+/// transparent page for OBS browser sources that shows the latest
+/// [`ScoreboardServer::publish_overlay`], kept up to date through `/overlay/ws`.
+///
+/// Started with [`ScoreboardServer::start_with_commands`], it also takes
+/// [`Transition`]s as JSON from `POST /command`, with an `Authorization: Bearer <token>`
+/// header, and serves a page of buttons for them at `/remote`. The server stops when it
+/// is dropped.
+pub struct ScoreboardServer {
+    server: Arc<tiny_http::Server>,
+    feed: Arc<Feed>,
+    commands: Option<mpsc::Receiver<RemoteCommand>>,
+    port: u16,
+}
+
+/// A transition sent over the network, waiting for the match to apply it.
+pub struct RemoteCommand {
+    pub event: Transition,
+    reply: mpsc::Sender<Result<MatchState, String>>,
+}
+
+impl RemoteCommand {
+    /// Tell the device that sent the command what became of it.
+    pub fn reply(self, result: Result<MatchState, TransitionError>) {
+        // The device may have given up waiting.
+        self.reply
+            .send(result.map_err(|error| error.to_string()))
+            .ok();
+    }
+
+    /// Turn the command down without applying it, telling the device why.
+    pub fn refuse(self, reason: &str) {
+        self.reply.send(Err(reason.to_owned())).ok();
+    }
+}
+
+/// Where the server hands remote commands over, and the token they need.
+#[derive(Clone)]
+struct Control {
+    token: Arc<str>,
+    commands: mpsc::Sender<RemoteCommand>,
+    wake: Arc<dyn Fn() + Send + Sync>,
+}
+
+/// The latest state, shared with every connection.
+#[derive(Default)]
+struct Feed {
+    latest: Mutex<Latest>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Latest {
+    /// Counts the changes, so a connection can tell whether it is behind.
+    version: u64,
+    json: String,
+    overlay: String,
+    closed: bool,
+}
+
+impl Feed {
+    fn latest(&self) -> MutexGuard<'_, Latest> {
+        self.latest.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replace the text `field` picks, and wake the connections if it changed.
+    fn update(&self, field: fn(&mut Latest) -> &mut String, text: String) {
+        let mut latest = self.latest();
+        let current = field(&mut latest);
+        if *current != text {
+            *current = text;
+            latest.version += 1;
+            self.changed.notify_all();
+        }
+    }
+}
+
+impl ScoreboardServer {
+    /// Listen on every interface on `port`, or on any free port if it is 0.
+    pub fn start(port: u16) -> io::Result<Self> {
+        Self::listen(port, None)
+    }
+
+    /// Like [`ScoreboardServer::start`], and take commands from devices that send
+    /// `token`. `wake` is called when one arrives, so that the match picks it up with
+    /// [`ScoreboardServer::next_command`].
+    pub fn start_with_commands(
+        port: u16,
+        token: &str,
+        wake: impl Fn() + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let control = Control {
+            token: token.into(),
+            commands: sender,
+            wake: Arc::new(wake),
+        };
+        let mut server = Self::listen(port, Some(control))?;
+        server.commands = Some(receiver);
+        Ok(server)
+    }
+
+    fn listen(port: u16, control: Option<Control>) -> io::Result<Self> {
+        let server = tiny_http::Server::http(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+        let port = server
+            .server_addr()
+            .to_ip()
+            .map_or(port, |address| address.port());
+        let server = Arc::new(server);
+        let feed = Arc::new(Feed::default());
+        {
+            let server = server.clone();
+            let feed = feed.clone();
+            thread::spawn(move || serve(&server, &feed, control.as_ref()));
+        }
+        Ok(Self {
+            server,
+            feed,
+            commands: None,
+            port,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Where other devices on the network find the scoreboard page.
+    pub fn url(&self) -> String {
+        format!("http://{}:{}/", local_ip(), self.port)
+    }
+
+    pub fn takes_commands(&self) -> bool {
+        self.commands.is_some()
+    }
+
+    /// The next command a device has sent, if any.
+    pub fn next_command(&self) -> Option<RemoteCommand> {
+        self.commands.as_ref()?.try_recv().ok()
+    }
+
+    /// Send `state` to every display, if it changed since the last call.
+    pub fn publish(&self, state: &LiveState) {
+        self.feed.update(|latest| &mut latest.json, state.to_json());
+    }
+
+    /// Show `html` on every overlay page, if it changed since the last call.
+    pub fn publish_overlay(&self, html: String) {
+        self.feed.update(|latest| &mut latest.overlay, html);
+    }
+}
+
+impl Drop for ScoreboardServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        self.feed.latest().closed = true;
+        self.feed.changed.notify_all();
+    }
+}
+
+fn serve(server: &tiny_http::Server, feed: &Arc<Feed>, control: Option<&Control>) {
+    for request in server.incoming_requests() {
+        if let Err(error) = respond(request, feed, control) {
+            log::warn!("Scoreboard request failed: {}", error);
+        }
+    }
+}
+
+fn respond(request: Request, feed: &Arc<Feed>, control: Option<&Control>) -> io::Result<()> {
+    match (request.method(), request.url(), control) {
+        (Method::Get, "/", _) => request.respond(
+            Response::from_string(SCOREBOARD_PAGE)
+                .with_header(header("Content-Type", "text/html; charset=utf-8")),
+        ),
+        (Method::Get, "/state", _) => {
+            let json = feed.latest().json.clone();
+            request.respond(json_response(200, json))
+        }
+        (Method::Get, "/ws", _) => upgrade(request, feed, |latest| &latest.json),
+        (Method::Get, "/overlay", _) => request.respond(
+            Response::from_string(OVERLAY_PAGE)
+                .with_header(header("Content-Type", "text/html; charset=utf-8")),
+        ),
+        (Method::Get, "/overlay/ws", _) => upgrade(request, feed, |latest| &latest.overlay),
+        (Method::Get, "/remote", Some(_)) => request.respond(
+            Response::from_string(REMOTE_PAGE)
+                .with_header(header("Content-Type", "text/html; charset=utf-8")),
+        ),
+        (Method::Post, "/command", Some(control)) => {
+            let control = control.clone();
+            // Waiting for the match must not hold up the displays.
+            thread::spawn(move || {
+                if let Err(error) = command(request, &control) {
+                    log::warn!("Remote command failed: {}", error);
+                }
+            });
+            Ok(())
+        }
+        _ => request.respond(Response::empty(404)),
+    }
+}
+
+/// Check a remote command, pass it to the match and answer with what became of it.
+fn command(mut request: Request, control: &Control) -> io::Result<()> {
+    let bearer = format!("Bearer {}", control.token);
+    let authorized = request.headers().iter().any(|header| {
+        header.field.equiv("Authorization") && same_secret(header.value.as_str(), &bearer)
+    });
+    if !authorized {
+        return request.respond(error_response(401, "missing or wrong token"));
+    }
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_COMMAND_LENGTH)
+        .read_to_string(&mut body)?;
+    let event: Transition = match serde_json::from_str(&body) {
+        Ok(event) => event,
+        Err(error) => return request.respond(error_response(400, &error.to_string())),
+    };
+    let (reply, result) = mpsc::channel();
+    if control
+        .commands
+        .send(RemoteCommand { event, reply })
+        .is_err()
+    {
+        return request.respond(error_response(503, "the match is not taking commands"));
+    }
+    (control.wake)();
+    match result.recv_timeout(COMMAND_TIMEOUT) {
+        Ok(Ok(state)) => request.respond(json_response(
+            200,
+            serde_json::to_string(&state).expect("a match state is JSON friendly"),
+        )),
+        Ok(Err(error)) => request.respond(error_response(409, &error)),
+        Err(_) => request.respond(error_response(503, "the match did not take the command")),
+    }
+}
+
+/// Compare a secret in a time that doesn't depend on where the first wrong byte is, so the
+/// token can't be guessed one byte at a time.
+fn same_secret(given: &str, secret: &str) -> bool {
+    given.len() == secret.len()
+        && given
+            .bytes()
+            .zip(secret.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn json_response(status: u16, json: String) -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_string(json)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn error_response(status: u16, error: &str) -> Response<io::Cursor<Vec<u8>>> {
+    json_response(status, serde_json::json!({ "error": error }).to_string())
+}
+
+/// Accept a WebSocket and keep it fed with the text `field` picks, on its own thread.
+fn upgrade(request: Request, feed: &Arc<Feed>, field: fn(&Latest) -> &String) -> io::Result<()> {
+    let key = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+        .map(|header| derive_accept_key(header.value.as_bytes()));
+    let Some(accept) = key else {
+        return request.respond(Response::empty(400));
+    };
+    let response = Response::empty(101).with_header(header("Sec-WebSocket-Accept", &accept));
+    let stream = request.upgrade("websocket", response);
+    let feed = feed.clone();
+    thread::spawn(move || {
+        push(
+            WebSocket::from_raw_socket(stream, Role::Server, None),
+            &feed,
+            field,
+        )
+    });
+    Ok(())
+}
+
+/// Send every change of the text `field` picks down `socket`, until it goes away or the
+/// server stops.
+fn push<S: io::Read + io::Write>(
+    mut socket: WebSocket<S>,
+    feed: &Feed,
+    field: fn(&Latest) -> &String,
+) {
+    let mut seen = None;
+    let mut sent = None;
+    loop {
+        let text = {
+            let mut latest = feed.latest();
+            while !latest.closed && Some(latest.version) == seen {
+                latest = feed
+                    .changed
+                    .wait(latest)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            if latest.closed {
+                break;
+            }
+            seen = Some(latest.version);
+            let text = field(&latest);
+            if sent.as_ref() == Some(text) {
+                continue;
+            }
+            text.clone()
+        };
+        if socket.send(Message::Text(text.clone())).is_err() {
+            return;
+        }
+        sent = Some(text);
+    }
+    socket.close(None).ok();
+}
+
+fn header(field: &str, value: &str) -> tiny_http::Header {
+    tiny_http::Header::from_bytes(field, value).expect("header names and values are ASCII")
+}
+
+/// The address of the interface that reaches the rest of the network. Connecting a
+/// UDP socket picks the route without sending anything.
+fn local_ip() -> IpAddr {
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect((Ipv4Addr::new(8, 8, 8, 8), 80))?;
+            socket.local_addr()
+        })
+        .map_or(IpAddr::V4(Ipv4Addr::LOCALHOST), |address| address.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::engine::{MatchEngine, RegulationState};
+
+    fn get(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn post(port: u16, token: Option<&str>, body: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let authorization = token
+            .map(|token| format!("Authorization: Bearer {}\r\n", token))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "POST /command HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            authorization,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    /// Send `body` as a command, answering it with `engine` the way the app does.
+    fn command(server: &ScoreboardServer, engine: &mut MatchEngine, body: &'static str) -> String {
+        let port = server.port();
+        let request = thread::spawn(move || post(port, Some("secret"), body));
+        loop {
+            if let Some(command) = server.next_command() {
+                let result = engine.apply(command.event);
+                command.reply(result);
+            }
+            if request.is_finished() {
+                return request.join().unwrap();
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn displays_get_the_page_the_state_and_every_change() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        let server = ScoreboardServer::start(0).unwrap();
+        server.publish(&LiveState::new(&engine, engine.now()));
+
+        assert!(get(server.port(), "/").contains("<html"));
+        assert!(get(server.port(), "/state").contains(r#""regulation":"None""#));
+        assert!(get(server.port(), "/nowhere").starts_with("HTTP/1.1 404"));
+
+        let stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        let url = format!("ws://127.0.0.1:{}/ws", server.port());
+        let (mut socket, _) = tungstenite::client(url, stream).unwrap();
+        let first = socket.read().unwrap().into_text().unwrap();
+        assert!(first.contains(r#""regulation":"None""#));
+
+        engine.apply(Transition::StartRegulation).unwrap();
+        server.publish(&LiveState::new(&engine, engine.now()));
+        let second = socket.read().unwrap().into_text().unwrap();
+        assert!(second.contains(r#""regulation":"NotEngaged""#));
+    }
+
+    #[test]
+    fn overlay_pages_get_the_rendered_overlay() {
+        let server = ScoreboardServer::start(0).unwrap();
+        server.publish_overlay("<b>01:00</b>".to_owned());
+        assert!(get(server.port(), "/overlay").contains("transparent"));
+
+        let stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        let url = format!("ws://127.0.0.1:{}/overlay/ws", server.port());
+        let (mut socket, _) = tungstenite::client(url, stream).unwrap();
+        assert_eq!("<b>01:00</b>", socket.read().unwrap().into_text().unwrap());
+        // Only the overlay changing reaches an overlay page.
+        server.publish_overlay("<b>00:59</b>".to_owned());
+        assert_eq!("<b>00:59</b>", socket.read().unwrap().into_text().unwrap());
+    }
+
+    #[test]
+    fn commands_need_the_token_and_a_legal_transition() {
+        let clock = ManualClock::new();
+        let mut engine = MatchEngine::default().with_clock(clock.clone());
+        let server = ScoreboardServer::start_with_commands(0, "secret", || {}).unwrap();
+        let port = server.port();
+
+        assert!(post(port, None, r#""StartRegulation""#).starts_with("HTTP/1.1 401"));
+        assert!(post(port, Some("guess"), r#""StartRegulation""#).starts_with("HTTP/1.1 401"));
+        assert!(post(port, Some("secret"), r#""Dance""#).starts_with("HTTP/1.1 400"));
+        assert_eq!(RegulationState::None, engine.state());
+
+        let response = command(&server, &mut engine, r#""Engage""#);
+        assert!(response.starts_with("HTTP/1.1 409"), "{}", response);
+        assert!(response.contains("not allowed"));
+
+        let response = command(&server, &mut engine, r#""StartRegulation""#);
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains(r#""regulation":"NotEngaged""#));
+        assert_eq!(RegulationState::NotEngaged, engine.state());
+    }
+
+    #[test]
+    fn a_scoreboard_only_server_takes_no_commands() {
+        let server = ScoreboardServer::start(0).unwrap();
+        assert!(!server.takes_commands());
+        assert!(post(server.port(), Some("secret"), r#""Engage""#).starts_with("HTTP/1.1 404"));
+        assert!(get(server.port(), "/remote").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(same_secret("Bearer secret", "Bearer secret"));
+        assert!(!same_secret("Bearer secreT", "Bearer secret"));
+        assert!(!same_secret("Bearer secret2", "Bearer secret"));
+        assert!(!same_secret("", "Bearer secret"));
+    }
+}
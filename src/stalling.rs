@@ -0,0 +1,56 @@
+use web_time::Duration;
+
+use crate::engine::Fighter;
+
+/// Who the officials hold responsible for the fighters not being engaged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Passivity {
+    #[default]
+    Both,
+    Fighter(Fighter),
+}
+
+impl Passivity {
+    pub fn includes(self, fighter: Fighter) -> bool {
+        match self {
+            Self::Both => true,
+            Self::Fighter(responsible) => responsible == fighter,
+        }
+    }
+}
+
+/// A time for each fighter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct FighterTimes {
+    pub a: Duration,
+    pub b: Duration,
+}
+
+impl FighterTimes {
+    pub fn get(&self, fighter: Fighter) -> Duration {
+        match fighter {
+            Fighter::A => self.a,
+            Fighter::B => self.b,
+        }
+    }
+
+    /// Count `duration` for every fighter `passivity` holds responsible.
+    pub fn add(&mut self, passivity: Passivity, duration: Duration) {
+        if passivity.includes(Fighter::A) {
+            self.a += duration;
+        }
+        if passivity.includes(Fighter::B) {
+            self.b += duration;
+        }
+    }
+
+    /// Take `duration` back from every fighter `passivity` holds responsible.
+    pub fn remove(&mut self, passivity: Passivity, duration: Duration) {
+        if passivity.includes(Fighter::A) {
+            self.a = self.a.saturating_sub(duration);
+        }
+        if passivity.includes(Fighter::B) {
+            self.b = self.b.saturating_sub(duration);
+        }
+    }
+}